@@ -0,0 +1,26 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(model.position.x, model.position.y, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // in the fragment shader, position holds the pixel coordinates
+    // squares are 8 pixels across
+    let cell = floor(in.position.xy / 8.0);
+    let light = (i32(cell.x) + i32(cell.y)) % 2 == 0;
+    return select(vec4<f32>(0.35, 0.35, 0.35, 1.0), vec4<f32>(0.6, 0.6, 0.6, 1.0), light);
+}
@@ -1,11 +1,11 @@
 struct VertexInput {
     @location(0) position: vec2<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) color: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) color: vec4<f32>,
 }
 
 @vertex
@@ -25,5 +25,5 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color.b, in.color.g, in.color.r, 1.0);
+    return vec4<f32>(in.color.b, in.color.g, in.color.r, in.color.a);
 }
\ No newline at end of file
@@ -0,0 +1,55 @@
+struct Gradient {
+    // rgb is the (linear) color of the stop, a is its offset
+    stops: array<vec4<f32>, 8>,
+    // linear: from.xy, to.xy. radial: center.xy, radius, unused
+    geometry: vec4<f32>,
+    // kind (0 = linear, 1 = radial), number of stops, aspect ratio, unused
+    info: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(1) pos: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> gradient: Gradient;
+
+// a single triangle that covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32(i32(index & 1u) * 4 - 1);
+    let y = f32(i32(index >> 1u) * 4 - 1);
+    out.position = vec4<f32>(x, y, 0.0, 1.0);
+    out.pos = vec2<f32>(x, y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // measure distances in units of the frame height, so radial gradients are round
+    let aspect = vec2<f32>(gradient.info.z, 1.0);
+    let p = in.pos * aspect;
+    var t: f32;
+    if gradient.info.x < 0.5 {
+        let a = gradient.geometry.xy * aspect;
+        let b = gradient.geometry.zw * aspect;
+        let d = b - a;
+        t = dot(p - a, d) / max(dot(d, d), 0.000001);
+    } else {
+        t = length(p - gradient.geometry.xy * aspect) / max(gradient.geometry.z, 0.000001);
+    }
+
+    let count = i32(gradient.info.y);
+    var color = gradient.stops[0].rgb;
+    for (var i = 1; i < count; i++) {
+        let prev = gradient.stops[i - 1];
+        let next = gradient.stops[i];
+        if t > prev.a {
+            let s = clamp((t - prev.a) / max(next.a - prev.a, 0.000001), 0.0, 1.0);
+            color = mix(prev.rgb, next.rgb, s);
+        }
+    }
+    return vec4<f32>(color.b, color.g, color.r, 1.0);
+}
@@ -1,11 +1,13 @@
 struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) tint: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) tint: vec4<f32>,
 }
 
 @vertex
@@ -15,6 +17,7 @@ fn vs_main(
     var out: VertexOutput;
     out.position = vec4<f32>(model.position.x, model.position.y, 0.0, 1.0);
     out.tex_coords = model.tex_coords;
+    out.tint = model.tint;
     return out;
 }
 
@@ -25,5 +28,6 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // textures are uploaded with red and blue swapped, so the tint has to be swapped too
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.tint.bgra;
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
 struct VertexInput {
     @location(0) position: vec2<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) tint: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) tint: vec4<f32>,
 }
 
 @vertex
@@ -15,6 +17,7 @@ fn vs_main(
     var out: VertexOutput;
     out.position = vec4<f32>(model.position.x, model.position.y, 0.0, 1.0);
     out.tex_coords = model.tex_coords;
+    out.tint = model.tint;
     return out;
 }
 
@@ -27,5 +30,5 @@ var s_diffuse: sampler;
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let tex_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     // screen expects bgr, but image crate only returns rgb, so flip r and b here
-    return vec4<f32>(tex_color.b, tex_color.g, tex_color.r, tex_color.a);
+    return vec4<f32>(tex_color.b, tex_color.g, tex_color.r, tex_color.a) * in.tint;
 }
\ No newline at end of file
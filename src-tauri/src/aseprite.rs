@@ -0,0 +1,384 @@
+/// Reading Aseprite (`.aseprite`/`.ase`) files into animations, following
+/// https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md. Every frame is
+/// flattened from the visible layers, and every image layer is also kept on its own, so parts
+/// of a sprite can be drawn separately.
+///
+/// Layers are always blended normally (other blend modes are treated as normal), and tilemap
+/// layers are skipped.
+use anyhow::{anyhow, Context, Result};
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use std::{fs, io::Read, path::Path};
+
+use crate::reader::{Endian, Reader};
+use crate::signals::{Animation, AnimationLayer, AnimationTag, TagDirection};
+
+const FILE_MAGIC: u16 = 0xa5e0;
+const FRAME_MAGIC: u16 = 0xf1fa;
+
+const OLD_PALETTE_CHUNK: u16 = 0x0004;
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const TAGS_CHUNK: u16 = 0x2018;
+const PALETTE_CHUNK: u16 = 0x2019;
+
+const LAYER_VISIBLE: u16 = 1;
+const LAYER_GROUP: u16 = 1;
+const LAYER_TILEMAP: u16 = 2;
+const HEADER_HAS_OPACITY: u32 = 1;
+
+/// A string, stored after its length
+fn string(r: &mut Reader) -> Result<String> {
+    let len = r.u16()? as usize;
+    Ok(String::from_utf8_lossy(r.bytes(len)?).into_owned())
+}
+
+struct Layer {
+    name: String,
+    /// whether it and all the groups it's in are visible
+    visible: bool,
+    /// groups hold other layers but have no cels
+    group: bool,
+    /// tilemaps aren't supported, they're left out of frames and layers
+    tilemap: bool,
+    opacity: u8,
+}
+
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    z_index: i16,
+    image: RgbaImage,
+}
+
+/// How pixels are stored, which decides how they turn into RGBA
+#[derive(Clone, Copy)]
+enum ColorDepth {
+    Rgba,
+    Grayscale,
+    /// palette indices, with the index that's transparent
+    Indexed(u8),
+}
+
+impl ColorDepth {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorDepth::Rgba => 4,
+            ColorDepth::Grayscale => 2,
+            ColorDepth::Indexed(_) => 1,
+        }
+    }
+}
+
+pub fn load_aseprite(path: &Path) -> Result<Animation> {
+    let data = fs::read(path).with_context(|| format!("can't open {}", path.display()))?;
+    parse(&data).with_context(|| format!("can't read aseprite file {}", path.display()))
+}
+
+fn parse(data: &[u8]) -> Result<Animation> {
+    let mut r = Reader::new(data, Endian::Little);
+    r.u32()?; // file size
+    if r.u16()? != FILE_MAGIC {
+        return Err(anyhow!("not an aseprite file"));
+    }
+    let frame_count = r.u16()? as usize;
+    let (width, height) = (r.u16()? as u32, r.u16()? as u32);
+    let depth = r.u16()?;
+    let header_flags = r.u32()?;
+    r.bytes(10)?; // speed (deprecated) and two zeros
+    let transparent_index = r.byte()?;
+    let depth = match depth {
+        32 => ColorDepth::Rgba,
+        16 => ColorDepth::Grayscale,
+        8 => ColorDepth::Indexed(transparent_index),
+        depth => return Err(anyhow!("unknown color depth {}", depth)),
+    };
+    r.pos = 128;
+
+    let mut layers: Vec<Layer> = vec![];
+    // visibility of the groups the next layer can be in, by child level
+    let mut groups_visible: Vec<bool> = vec![];
+    let mut palette = vec![Rgba([0, 0, 0, 0]); 256];
+    let mut has_new_palette = false;
+    let mut tags = vec![];
+    let mut durations = vec![];
+    // cels of every frame, as drawn in that frame
+    let mut frames: Vec<Vec<Cel>> = vec![];
+
+    for _ in 0..frame_count {
+        let frame_start = r.pos;
+        let frame_size = r.u32()? as usize;
+        if r.u16()? != FRAME_MAGIC {
+            return Err(anyhow!("frame {} is corrupt", frames.len()));
+        }
+        let old_chunk_count = r.u16()? as usize;
+        durations.push(r.u16()? as f32 / 1000.0);
+        r.bytes(2)?;
+        let chunk_count = match r.u32()? as usize {
+            0 => old_chunk_count,
+            count => count,
+        };
+
+        let mut cels = vec![];
+        for _ in 0..chunk_count {
+            let chunk_start = r.pos;
+            let chunk_size = r.u32()? as usize;
+            let chunk_type = r.u16()?;
+            let chunk = data
+                .get(r.pos..chunk_start + chunk_size)
+                .ok_or(anyhow!("unexpected end of file"))?;
+            let mut c = Reader::new(chunk, Endian::Little);
+            match chunk_type {
+                LAYER_CHUNK => {
+                    let flags = c.u16()?;
+                    let kind = c.u16()?;
+                    let level = c.u16()? as usize;
+                    c.bytes(6)?; // default size and blend mode
+                    let opacity = c.byte()?;
+                    c.bytes(3)?;
+                    let name = string(&mut c)?;
+
+                    groups_visible.truncate(level);
+                    let visible = flags & LAYER_VISIBLE != 0 && groups_visible.iter().all(|v| *v);
+                    let group = kind == LAYER_GROUP;
+                    if group {
+                        groups_visible.push(flags & LAYER_VISIBLE != 0);
+                    }
+                    layers.push(Layer {
+                        name,
+                        visible,
+                        group,
+                        tilemap: kind == LAYER_TILEMAP,
+                        opacity: if header_flags & HEADER_HAS_OPACITY != 0 {
+                            opacity
+                        } else {
+                            255
+                        },
+                    });
+                }
+                CEL_CHUNK => {
+                    if let Some(cel) = read_cel(&mut c, depth, &palette, &frames)? {
+                        cels.push(cel);
+                    }
+                }
+                TAGS_CHUNK => {
+                    let count = c.u16()?;
+                    c.bytes(8)?;
+                    for _ in 0..count {
+                        let (from, to) = (c.u16()? as usize, c.u16()? as usize);
+                        // a broken file could have them the wrong way around
+                        let (from, to) = (from.min(to), from.max(to));
+                        let direction = match c.byte()? {
+                            1 => TagDirection::Reverse,
+                            2 | 3 => TagDirection::PingPong,
+                            _ => TagDirection::Forward,
+                        };
+                        c.bytes(12)?; // repeat count, reserved and color
+                        let name = string(&mut c)?;
+                        tags.push(AnimationTag {
+                            name,
+                            from,
+                            to,
+                            direction,
+                        });
+                    }
+                }
+                PALETTE_CHUNK => {
+                    has_new_palette = true;
+                    let size = c.u32()? as usize;
+                    palette.resize(size.max(palette.len()), Rgba([0, 0, 0, 0]));
+                    let (first, last) = (c.u32()? as usize, c.u32()? as usize);
+                    c.bytes(8)?;
+                    for i in first..=last {
+                        let flags = c.u16()?;
+                        let rgba = c.bytes(4)?;
+                        if let Some(entry) = palette.get_mut(i) {
+                            *entry = Rgba([rgba[0], rgba[1], rgba[2], rgba[3]]);
+                        }
+                        if flags & 1 != 0 {
+                            string(&mut c)?;
+                        }
+                    }
+                }
+                // only older files have just this one
+                OLD_PALETTE_CHUNK if !has_new_palette => {
+                    let mut i = 0;
+                    for _ in 0..c.u16()? {
+                        i += c.byte()? as usize;
+                        let count = match c.byte()? {
+                            0 => 256,
+                            count => count as usize,
+                        };
+                        for _ in 0..count {
+                            let rgb = c.bytes(3)?;
+                            if let Some(entry) = palette.get_mut(i) {
+                                *entry = Rgba([rgb[0], rgb[1], rgb[2], 255]);
+                            }
+                            i += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            r.pos = chunk_start + chunk_size;
+        }
+        frames.push(cels);
+        r.pos = frame_start + frame_size;
+    }
+
+    if frames.is_empty() {
+        return Err(anyhow!("no frames"));
+    }
+
+    let flattened = frames
+        .iter()
+        .map(|cels| {
+            let visible = cels.iter().filter(|cel| {
+                layers
+                    .get(cel.layer)
+                    .is_some_and(|layer| layer.visible && !layer.group && !layer.tilemap)
+            });
+            DynamicImage::ImageRgba8(flatten(width, height, visible, &layers))
+        })
+        .collect();
+    let image_layers = layers
+        .iter()
+        .enumerate()
+        .filter(|(_, layer)| !layer.group && !layer.tilemap)
+        .map(|(index, layer)| AnimationLayer {
+            name: layer.name.clone(),
+            frames: frames
+                .iter()
+                .map(|cels| {
+                    let cels = cels.iter().filter(|cel| cel.layer == index);
+                    DynamicImage::ImageRgba8(flatten(width, height, cels, &layers))
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Animation {
+        frames: flattened,
+        durations,
+        tags,
+        layers: image_layers,
+    })
+}
+
+/// Read a cel chunk. Linked cels are copies of a cel in an earlier frame.
+fn read_cel(
+    c: &mut Reader,
+    depth: ColorDepth,
+    palette: &[Rgba<u8>],
+    frames: &[Vec<Cel>],
+) -> Result<Option<Cel>> {
+    let layer = c.u16()? as usize;
+    let (x, y) = (c.i16()? as i32, c.i16()? as i32);
+    let opacity = c.byte()?;
+    let kind = c.u16()?;
+    let z_index = c.i16()?;
+    c.bytes(5)?;
+
+    let image = match kind {
+        0 | 2 => {
+            let (w, h) = (c.u16()? as u32, c.u16()? as u32);
+            let size = (w * h) as usize * depth.bytes_per_pixel();
+            let pixels = if kind == 0 {
+                c.bytes(size)?.to_vec()
+            } else {
+                let mut pixels = Vec::with_capacity(size);
+                ZlibDecoder::new(c.rest()).read_to_end(&mut pixels)?;
+                pixels
+            };
+            if pixels.len() < size {
+                return Err(anyhow!("cel on layer {} is missing pixels", layer));
+            }
+            to_rgba(w, h, &pixels, depth, palette)
+        }
+        1 => {
+            let frame = c.u16()? as usize;
+            let linked = frames
+                .get(frame)
+                .and_then(|cels| cels.iter().find(|cel| cel.layer == layer));
+            match linked {
+                Some(cel) => cel.image.clone(),
+                None => return Ok(None),
+            }
+        }
+        // tilemaps
+        _ => return Ok(None),
+    };
+    Ok(Some(Cel {
+        layer,
+        x,
+        y,
+        opacity,
+        z_index,
+        image,
+    }))
+}
+
+fn to_rgba(w: u32, h: u32, pixels: &[u8], depth: ColorDepth, palette: &[Rgba<u8>]) -> RgbaImage {
+    let size = depth.bytes_per_pixel();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let i = (y * w + x) as usize * size;
+        let p = &pixels[i..i + size];
+        match depth {
+            ColorDepth::Rgba => Rgba([p[0], p[1], p[2], p[3]]),
+            ColorDepth::Grayscale => Rgba([p[0], p[0], p[0], p[1]]),
+            ColorDepth::Indexed(transparent) if p[0] == transparent => Rgba([0, 0, 0, 0]),
+            ColorDepth::Indexed(_) => palette
+                .get(p[0] as usize)
+                .copied()
+                .unwrap_or(Rgba([0, 0, 0, 0])),
+        }
+    })
+}
+
+/// Draw cels over each other on a transparent canvas, in Aseprite's order: by layer, moved
+/// up or down by their z index
+fn flatten<'c>(
+    width: u32,
+    height: u32,
+    cels: impl Iterator<Item = &'c Cel>,
+    layers: &[Layer],
+) -> RgbaImage {
+    let mut cels: Vec<_> = cels.collect();
+    cels.sort_by_key(|cel| (cel.layer as i32 + cel.z_index as i32, cel.z_index));
+    let mut canvas = RgbaImage::new(width, height);
+    for cel in cels {
+        let opacity = cel.opacity as f32 / 255.0 * layers[cel.layer].opacity as f32 / 255.0;
+        for (x, y, pixel) in cel.image.enumerate_pixels() {
+            let (cx, cy) = (cel.x + x as i32, cel.y + y as i32);
+            if cx < 0 || cy < 0 || cx >= width as i32 || cy >= height as i32 {
+                continue;
+            }
+            let below = canvas.get_pixel_mut(cx as u32, cy as u32);
+            *below = over(*pixel, *below, opacity);
+        }
+    }
+    canvas
+}
+
+/// `top` drawn over `bottom`, both with straight alpha
+fn over(top: Rgba<u8>, bottom: Rgba<u8>, opacity: f32) -> Rgba<u8> {
+    let top_alpha = top[3] as f32 / 255.0 * opacity;
+    let bottom_alpha = bottom[3] as f32 / 255.0;
+    let alpha = top_alpha + bottom_alpha * (1.0 - top_alpha);
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| {
+        let mixed = top[i] as f32 * top_alpha + bottom[i] as f32 * bottom_alpha * (1.0 - top_alpha);
+        (mixed / alpha).round() as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha * 255.0).round() as u8,
+    ])
+}
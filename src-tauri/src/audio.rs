@@ -0,0 +1,177 @@
+/// Sound files used as resources (dialogue, music, effects). They're decoded in full when
+/// they're loaded, and resampled to one sample rate and to stereo, so the mixer can add them
+/// up without converting anything.
+use anyhow::{anyhow, Context, Result};
+use rubato::{FftFixedIn, Resampler};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use std::{fs::File, io::ErrorKind, path::Path};
+
+/// What every sound is resampled to
+pub const SAMPLE_RATE: u32 = 48_000;
+/// Frames of audio resampled at a time
+const RESAMPLE_CHUNK: usize = 1024;
+
+pub struct Audio {
+    /// left and right samples, at `SAMPLE_RATE`
+    pub samples: Vec<[f32; 2]>,
+}
+
+impl Audio {
+    pub fn open(path: &Path) -> Result<Audio> {
+        let name = path.display();
+        let file = File::open(path).with_context(|| format!("can't open {}", name))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let mut format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .with_context(|| format!("{} isn't a supported sound file", name))?
+            .format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(anyhow!("{} has no audio track", name))?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .with_context(|| format!("can't decode the audio in {}", name))?;
+
+        let mut samples = vec![];
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e).with_context(|| format!("can't read {}", name)),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // a damaged packet only loses a few milliseconds
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(e).with_context(|| format!("can't decode {}", name)),
+            };
+            let spec = *decoded.spec();
+            sample_rate = Some(spec.rate);
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            // mono plays on both sides, and only the front left and right of surround sound
+            // are kept
+            let channels = spec.channels.count();
+            samples.extend(
+                buffer
+                    .samples()
+                    .chunks_exact(channels)
+                    .map(|frame| match frame {
+                        [mono] => [*mono, *mono],
+                        [left, right, ..] => [*left, *right],
+                        [] => [0.0, 0.0],
+                    }),
+            );
+        }
+
+        let sample_rate = sample_rate.ok_or(anyhow!("{} has no sample rate", name))?;
+        let samples =
+            resample(samples, sample_rate).with_context(|| format!("can't resample {}", name))?;
+        Ok(Audio { samples })
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / SAMPLE_RATE as f32
+    }
+}
+
+/// Resample stereo `samples` from `sample_rate` to `SAMPLE_RATE`
+fn resample(samples: Vec<[f32; 2]>, sample_rate: u32) -> Result<Vec<[f32; 2]>> {
+    if sample_rate == SAMPLE_RATE || samples.is_empty() {
+        return Ok(samples);
+    }
+    let mut resampler = FftFixedIn::<f32>::new(
+        sample_rate as usize,
+        SAMPLE_RATE as usize,
+        RESAMPLE_CHUNK,
+        2,
+        2,
+    )?;
+    let expected = (samples.len() as u64 * SAMPLE_RATE as u64 / sample_rate as u64) as usize;
+    // the resampler works on each channel separately
+    let left: Vec<f32> = samples.iter().map(|s| s[0]).collect();
+    let right: Vec<f32> = samples.iter().map(|s| s[1]).collect();
+
+    let mut output: [Vec<f32>; 2] = [vec![], vec![]];
+    let append = |output: &mut [Vec<f32>; 2], chunk: Vec<Vec<f32>>| {
+        for (channel, resampled) in output.iter_mut().zip(chunk) {
+            channel.extend(resampled);
+        }
+    };
+    let mut start = 0;
+    while samples.len() - start >= resampler.input_frames_next() {
+        let end = start + resampler.input_frames_next();
+        append(
+            &mut output,
+            resampler.process(&[&left[start..end], &right[start..end]], None)?,
+        );
+        start = end;
+    }
+    append(
+        &mut output,
+        resampler.process_partial(Some(&[&left[start..], &right[start..]]), None)?,
+    );
+    // push out what's still in the resampler
+    let delay = resampler.output_delay();
+    while output[0].len() < expected + delay {
+        append(
+            &mut output,
+            resampler.process_partial::<&[f32]>(None, None)?,
+        );
+    }
+
+    let [left, right] = output;
+    Ok(left
+        .into_iter()
+        .zip(right)
+        .skip(delay)
+        .take(expected)
+        .map(|(l, r)| [l, r])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resampling_keeps_the_duration_and_timing() {
+        // shorter than a chunk, so it all goes through the partial and flushing calls
+        let mut samples = vec![[0.0; 2]; 441];
+        samples[100] = [1.0, -1.0];
+        let resampled = resample(samples, 44_100).unwrap();
+        assert_eq!(resampled.len(), 480);
+        // with the resampler's delay trimmed off the click stays where it was
+        let peak = (0..resampled.len())
+            .max_by(|&a, &b| resampled[a][0].total_cmp(&resampled[b][0]))
+            .unwrap();
+        assert!(peak.abs_diff(109) <= 1, "the click moved to {}", peak);
+        assert!(resampled[peak][1] < 0.0);
+    }
+}
@@ -0,0 +1,163 @@
+/// Finding the beat of a piece of music, and where its notes and hits start, so cuts and
+/// bounces can be put on them. Both come from the spectral flux: how much louder each
+/// frequency gets from one moment to the next. The beat is taken to be steady, which suits
+/// most music made to a click.
+use crate::audio::{Audio, SAMPLE_RATE};
+use crate::interface::Beats;
+use crate::spectrum::{mono, Spectrum};
+
+/// Samples between measurements of the flux
+const HOP: usize = 512;
+/// Samples measured each time
+const WINDOW: usize = 1024;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// When the beat could be at either of two tempos, like 70 and 140 bpm, the one closer to
+/// this wins
+const PREFERRED_BPM: f32 = 120.0;
+/// An onset is the biggest jump in flux this many seconds either side of it
+const PEAK_RADIUS: f32 = 0.05;
+/// ...and stands out from the average flux over this many seconds either side
+const MEAN_RADIUS: f32 = 0.1;
+/// by this much, with the flux going from 0 to 1
+const THRESHOLD: f32 = 0.05;
+
+pub fn beats(audio: &Audio, fps: usize) -> Beats {
+    let fps = fps.max(1);
+    let flux = spectral_flux(&mono(&audio.samples));
+    let hops_per_second = SAMPLE_RATE as f32 / HOP as f32;
+    let to_frames = |hops: Vec<f32>| {
+        let mut frames: Vec<usize> = hops
+            .into_iter()
+            .map(|hop| (hop / hops_per_second * fps as f32).round() as usize)
+            .collect();
+        frames.dedup();
+        frames
+    };
+
+    let onsets = onsets(&flux, hops_per_second);
+    let (tempo, beats) = match beat_period(&flux, hops_per_second) {
+        Some(period) => (60.0 * hops_per_second / period, beat_grid(&flux, period)),
+        None => (0.0, vec![]),
+    };
+    Beats {
+        tempo,
+        beats: to_frames(beats),
+        onsets: to_frames(onsets),
+    }
+}
+
+/// The flux every `HOP` samples, scaled to go from 0 to 1
+fn spectral_flux(samples: &[f32]) -> Vec<f32> {
+    let spectrum = Spectrum::new(WINDOW);
+    let mut previous: Option<Vec<f32>> = None;
+    let mut flux: Vec<f32> = (0..samples.len())
+        .step_by(HOP)
+        .map(|center| {
+            let start = center.saturating_sub(WINDOW / 2);
+            let end = (center + WINDOW / 2).min(samples.len());
+            // compressed, so quiet notes count as well as loud ones
+            let magnitudes: Vec<f32> = spectrum
+                .magnitudes(&samples[start..end])
+                .into_iter()
+                .map(|magnitude| magnitude.ln_1p())
+                .collect();
+            let flux = match &previous {
+                Some(previous) => magnitudes
+                    .iter()
+                    .zip(previous)
+                    .map(|(now, before)| (now - before).max(0.0))
+                    .sum(),
+                None => 0.0,
+            };
+            previous = Some(magnitudes);
+            flux
+        })
+        .collect();
+    let max = flux.iter().copied().fold(0.0, f32::max);
+    if max > 0.0 {
+        flux.iter_mut().for_each(|f| *f /= max);
+    }
+    flux
+}
+
+/// The hops where the flux peaks
+fn onsets(flux: &[f32], hops_per_second: f32) -> Vec<f32> {
+    let peak_radius = (PEAK_RADIUS * hops_per_second).round() as usize;
+    let mean_radius = (MEAN_RADIUS * hops_per_second).round() as usize;
+    let around =
+        |i: usize, radius: usize| &flux[i.saturating_sub(radius)..(i + radius + 1).min(flux.len())];
+    let mut onsets: Vec<f32> = vec![];
+    for (i, &f) in flux.iter().enumerate() {
+        let peak = around(i, peak_radius).iter().copied().fold(0.0, f32::max);
+        let window = around(i, mean_radius);
+        let mean = window.iter().sum::<f32>() / window.len() as f32;
+        // a flat peak only counts once
+        let too_close = onsets
+            .last()
+            .is_some_and(|&last| i as f32 - last <= peak_radius as f32);
+        if f > 0.0 && f == peak && f >= mean + THRESHOLD && !too_close {
+            onsets.push(i as f32);
+        }
+    }
+    onsets
+}
+
+/// Hops between beats: whichever tempo lines the flux up with itself best
+fn beat_period(flux: &[f32], hops_per_second: f32) -> Option<f32> {
+    let min_lag = ((60.0 / MAX_BPM * hops_per_second).floor() as usize).max(1);
+    let max_lag = (60.0 / MIN_BPM * hops_per_second).ceil() as usize;
+    if flux.len() <= max_lag + 1 {
+        return None;
+    }
+    let mean = flux.iter().sum::<f32>() / flux.len() as f32;
+    let centered: Vec<f32> = flux.iter().map(|f| f - mean).collect();
+    let score = |lag: usize| {
+        let correlation = centered
+            .iter()
+            .zip(&centered[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            / (centered.len() - lag) as f32;
+        let octaves = (60.0 * hops_per_second / lag as f32 / PREFERRED_BPM).log2();
+        correlation * (-0.5 * octaves * octaves).exp()
+    };
+    let scores: Vec<f32> = (min_lag - 1..=max_lag + 1).map(score).collect();
+    let (best, &best_score) = scores[1..scores.len() - 1]
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    if best_score <= 0.0 {
+        return None;
+    }
+    // fit a parabola through the best lag and its neighbours, for a tempo between whole hops
+    let [before, at, after] = [scores[best], scores[best + 1], scores[best + 2]];
+    let curve = before - 2.0 * at + after;
+    let offset = if curve < 0.0 {
+        0.5 * (before - after) / curve
+    } else {
+        0.0
+    };
+    Some((min_lag + best) as f32 + offset)
+}
+
+/// Beats every `period` hops, lined up with the flux as well as they can be
+fn beat_grid(flux: &[f32], period: f32) -> Vec<f32> {
+    let grid = |offset: f32| {
+        (0..)
+            .map(move |k| offset + k as f32 * period)
+            .take_while(|&hop| (hop.round() as usize) < flux.len())
+    };
+    let best_offset = (0..period.ceil() as usize)
+        .map(|offset| offset as f32)
+        .max_by(|&a, &b| {
+            let strength = |offset| {
+                grid(offset)
+                    .map(|hop| flux[hop.round() as usize])
+                    .sum::<f32>()
+            };
+            strength(a).total_cmp(&strength(b))
+        })
+        .unwrap_or(0.0);
+    grid(best_offset).collect()
+}
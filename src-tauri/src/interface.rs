@@ -2,7 +2,9 @@
 /// and the Renderer. The UI will send requests in the form of a vector of `FrameDescription`s
 /// to make into video. Thus the UI's json requests need to conform to the format defined
 /// by these structs.
-use serde::Deserialize;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Deserialize)]
 pub struct Color {
@@ -27,9 +29,21 @@ impl Color {
         })
     }
 
+    pub fn to_linear_rgba(&self, alpha: f32) -> [f32; 4] {
+        let [r, g, b] = self.to_linear_rgb();
+        [r, g, b, alpha]
+    }
+
+    /// The render targets are BGRA, and everything drawn into them is swizzled to match, so
+    /// red and blue are swapped here too.
     pub fn to_wgpu_color(&self) -> wgpu::Color {
         let [r, g, b] = self.to_linear_rgb().map(|x| x.into());
-        wgpu::Color { r, g, b, a: 1.0 }
+        wgpu::Color {
+            r: b,
+            g,
+            b: r,
+            a: 1.0,
+        }
     }
 }
 
@@ -52,6 +66,12 @@ pub enum Object {
     Bezier(Bezier),
     Img(Img),
     Text(Text),
+    ParticleEmitter(ParticleEmitter),
+    SpeechBubble(SpeechBubble),
+    Morph(Morph),
+    Precomp(Precomp),
+    Video(Video),
+    Shape(Shape),
 }
 
 #[derive(Deserialize)]
@@ -60,7 +80,7 @@ pub struct Point {
     pub y: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -68,6 +88,53 @@ pub struct Rect {
     pub h: f32,
 }
 
+impl Rect {
+    /// The smallest rectangle holding all of `points`, if there are any
+    pub fn around(points: impl IntoIterator<Item = [f32; 2]>) -> Option<Rect> {
+        let (mut min, mut max) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+        for [x, y] in points {
+            min = [min[0].min(x), min[1].min(y)];
+            max = [max[0].max(x), max[1].max(y)];
+        }
+        (min[0] <= max[0] && min[1] <= max[1]).then(|| Rect {
+            x: min[0],
+            y: min[1],
+            w: max[0] - min[0],
+            h: max[1] - min[1],
+        })
+    }
+
+    pub fn corners(&self) -> [[f32; 2]; 4] {
+        [
+            [self.x, self.y],
+            [self.x + self.w, self.y],
+            [self.x + self.w, self.y + self.h],
+            [self.x, self.y + self.h],
+        ]
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::around(self.corners().into_iter().chain(other.corners())).unwrap()
+    }
+
+    /// grow the rectangle by `amount` on every side
+    pub fn inflate(&self, amount: f32) -> Rect {
+        Rect {
+            x: self.x - amount,
+            y: self.y - amount,
+            w: self.w + 2.0 * amount,
+            h: self.h + 2.0 * amount,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x <= other.x + other.w
+            && other.x <= self.x + self.w
+            && self.y <= other.y + other.h
+            && other.y <= self.y + self.h
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Transform {
     pub pos : Point,
@@ -127,6 +194,23 @@ impl Transformation2D {
         Transformation2D(result)
     }
 
+    /// The transformation that undoes this one, if there is one (a scale of 0 can't be undone)
+    pub fn inverse(&self) -> Option<Transformation2D> {
+        let m = &self.0;
+        // cofactors, transposed into the adjugate
+        let cofactor = |i: usize, j: usize| {
+            let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+            let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let det: f32 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let adjugate = [0, 1, 2].map(|i| [0, 1, 2].map(|j| cofactor(j, i)));
+        Some(Transformation2D(adjugate.map(|row| row.map(|x| x / det))))
+    }
+
     pub fn apply_to(&self, pt: [f32; 2]) -> [f32; 2] {
         let result: Vec<f32> = self.0.iter().map(|row| {
             row.iter().zip(&[pt[0], pt[1], 1.0]).map(|(a, b)| a * b).sum()
@@ -142,17 +226,130 @@ pub enum Alignment {
     Right,
 }
 
+/// Text wrapped to `width` (in the same units as an `Img`, an image with scale 1 is 1 wide)
+/// and centered on the origin.
 #[derive(Deserialize)]
 pub struct Text {
     pub content: String,
     pub alignment: Alignment,
     pub width: f32,
+    /// id of a font resource
+    pub font: u32,
+    /// height of a line, in units of the y axis
+    #[serde(default = "text_size")]
+    pub size: f32,
+    #[serde(default = "black")]
+    pub color: Color,
+}
+
+fn text_size() -> f32 {
+    0.1
+}
+
+fn black() -> Color {
+    Color::new(0, 0, 0)
+}
+
+#[derive(Deserialize)]
+pub enum BubbleStyle {
+    Rounded,
+    Cloud,
+    /// for shouting
+    Jagged,
+}
+
+/// A bubble sized to fit its text, centered on the origin, with a tail pointing at `tail`
+#[derive(Deserialize)]
+pub struct SpeechBubble {
+    pub text: Text,
+    pub style: BubbleStyle,
+    /// space between the text and the bubble's edge, in units of the y axis
+    #[serde(default = "bubble_padding")]
+    pub padding: f32,
+    /// where the tail points, for example a character's head
+    #[serde(default)]
+    pub tail: Option<Point>,
+    #[serde(default = "bubble_fill")]
+    pub fill: Color,
+    #[serde(default = "black")]
+    pub outline: Color,
+    /// in units of the y axis
+    #[serde(default = "bubble_outline_width")]
+    pub outline_width: f32,
+}
+
+fn bubble_padding() -> f32 {
+    0.05
+}
+
+fn bubble_fill() -> Color {
+    Color::new(255, 255, 255)
+}
+
+fn bubble_outline_width() -> f32 {
+    0.01
 }
 
 #[derive(Deserialize)]
 pub struct Img {
     pub id: u32,
     pub subrect: Option<Rect>,
+    #[serde(default)]
+    pub nine_slice: Option<NineSlice>,
+    /// which frame to show if the resource is animated, the first one if there's none
+    #[serde(default)]
+    pub frame: Option<AnimationTime>,
+    /// only draw this layer of an animated resource that has layers (aseprite files), rather
+    /// than all its visible layers together
+    #[serde(default)]
+    pub layer: Option<String>,
+    /// from 0 (invisible) to 1
+    #[serde(default = "one")]
+    pub opacity: f32,
+}
+
+/// A frame of an animated image resource. Animations loop, so frames and times past the end
+/// wrap around.
+#[derive(Deserialize)]
+pub enum AnimationTime {
+    Frame(usize),
+    /// seconds since the start of the animation, using its own frame durations
+    Time(f32),
+    /// seconds into the named loop of the animation (an aseprite tag), played in the loop's
+    /// direction. Unknown tags play the whole animation.
+    Tag {
+        tag: String,
+        time: f32,
+    },
+}
+
+/// A frame of a video clip resource, sized like an `Img` of the clip's frames
+#[derive(Deserialize)]
+pub struct Video {
+    pub id: u32,
+    /// frame of the clip (not of the video it's used in) to show. Past the end of the clip,
+    /// its last frame is shown.
+    pub source_frame: usize,
+}
+
+/// Draw an image as a box of any size, where the corners keep their size and only the edges
+/// and the middle stretch. Used for speech bubbles, panels, title cards and the like.
+/// `subrect` is ignored for nine slice images.
+#[derive(Deserialize)]
+pub struct NineSlice {
+    /// in pixels of the image
+    pub insets: Insets,
+    /// size of the box, in the same units as an `Img` (an image with scale 1 is 1 wide)
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Deserialize)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
 }
 
 #[derive(Deserialize)]
@@ -162,6 +359,174 @@ pub struct Bezier {
     pub points: [Point; 3],
 }
 
+#[derive(Deserialize)]
+pub struct RandomRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl RandomRange {
+    /// pick a value in the range, `t` being a random number between 0 and 1
+    pub fn at(&self, t: f32) -> f32 {
+        self.min + (self.max - self.min) * t
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OverLife<T> {
+    pub start: T,
+    pub end: T,
+}
+
+/// Where new particles appear, relative to the emitter
+#[derive(Deserialize)]
+pub enum EmitterShape {
+    Point,
+    Line { from: Point, to: Point },
+    Rect(Rect),
+    Circle { radius: f32 },
+}
+
+#[derive(Deserialize)]
+pub enum ParticleKind {
+    Img(u32),
+    Circle,
+    Square,
+}
+
+/// A particle system. The renderer works out where every particle is from `seed` and the
+/// frame being rendered alone, so the same emitter always looks the same at the same frame,
+/// both in the preview and the export. Speeds, spins and gravity are per frame, angles are in
+/// degrees.
+#[derive(Deserialize)]
+pub struct ParticleEmitter {
+    pub seed: u32,
+    /// frame of the video (or of the composition it's in) the emitter starts running at
+    #[serde(default)]
+    pub start: f32,
+    pub shape: EmitterShape,
+    /// particles emitted per frame
+    pub rate: f32,
+    /// stop emitting new particles after this many frames
+    #[serde(default)]
+    pub duration: Option<f32>,
+    /// in frames
+    pub lifetime: RandomRange,
+    pub speed: RandomRange,
+    pub direction: f32,
+    #[serde(default)]
+    pub spread: f32,
+    #[serde(default = "origin")]
+    pub gravity: Point,
+    #[serde(default = "no_spin")]
+    pub spin: RandomRange,
+    pub size: OverLife<f32>,
+    #[serde(default = "white")]
+    pub color: OverLife<Color>,
+    #[serde(default = "opaque")]
+    pub opacity: OverLife<f32>,
+    pub particle: ParticleKind,
+}
+
+fn origin() -> Point {
+    Point { x: 0.0, y: 0.0 }
+}
+
+fn no_spin() -> RandomRange {
+    RandomRange { min: 0.0, max: 0.0 }
+}
+
+fn white() -> OverLife<Color> {
+    OverLife {
+        start: Color::new(255, 255, 255),
+        end: Color::new(255, 255, 255),
+    }
+}
+
+fn opaque() -> OverLife<f32> {
+    OverLife {
+        start: 1.0,
+        end: 1.0,
+    }
+}
+
+#[derive(Deserialize)]
+pub enum Segment {
+    Line(Point),
+    Quadratic {
+        ctrl: Point,
+        to: Point,
+    },
+    Cubic {
+        ctrl1: Point,
+        ctrl2: Point,
+        to: Point,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct VectorPath {
+    pub start: Point,
+    pub segments: Vec<Segment>,
+    #[serde(default)]
+    pub closed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Stroke {
+    pub color: Color,
+    pub thickness: f32,
+}
+
+/// A filled and/or outlined shape made of one or more paths, like imported vector art. Unlike
+/// `Morph`, coordinates (and the stroke thickness) are in the units of the node it's in, the
+/// same as the node's position, so it moves and scales along with its siblings. How paths
+/// inside others cut holes depends on `fill_rule`.
+#[derive(Deserialize)]
+pub struct Shape {
+    pub paths: Vec<VectorPath>,
+    #[serde(default)]
+    pub fill: Option<Color>,
+    #[serde(default = "non_zero")]
+    pub fill_rule: FillRule,
+    #[serde(default)]
+    pub stroke: Option<Stroke>,
+}
+
+/// Which parts of overlapping paths are inside a shape, like svg's `fill-rule`
+#[derive(Deserialize, Clone, Copy)]
+pub enum FillRule {
+    /// paths inside others that wind the other way cut holes
+    NonZero,
+    /// paths inside others cut holes, whichever way they wind
+    EvenOdd,
+}
+
+fn non_zero() -> FillRule {
+    FillRule::NonZero
+}
+
+/// A shape part way between two paths, for mouth shapes, blobs and icon to icon changes.
+/// `t` goes from 0 (`from`) to 1 (`to`), going past either end overshoots. If either path is
+/// open, the result is too. Coordinates are in units of the y axis, like text.
+#[derive(Deserialize)]
+pub struct Morph {
+    pub from: VectorPath,
+    pub to: VectorPath,
+    pub t: f32,
+    #[serde(default)]
+    pub fill: Option<Color>,
+    #[serde(default)]
+    pub stroke: Option<Stroke>,
+    /// how many points both paths get resampled to, up to 1024
+    #[serde(default = "morph_samples")]
+    pub samples: usize,
+}
+
+fn morph_samples() -> usize {
+    128
+}
+
 #[derive(Deserialize)]
 pub struct AudioDescription {
     pub id: u32,
@@ -170,6 +535,53 @@ pub struct AudioDescription {
     pub end: Option<usize>,
     #[serde(default)]
     pub looping: bool,
+    /// gain, 1 leaves the sound as it is
+    #[serde(default = "one")]
+    pub volume: f32,
+    /// frames to fade in over, from the start
+    #[serde(default)]
+    pub fade_in: usize,
+    /// frames to fade out over, up to the end. A looping sound with no end never fades out.
+    #[serde(default)]
+    pub fade_out: usize,
+    /// volume over time, multiplied with `volume`. Keys are sorted by frame when they're read,
+    /// with the volume going in a straight line between them and held before the first and
+    /// after the last.
+    #[serde(default, deserialize_with = "envelope")]
+    pub envelope: Vec<VolumeKey>,
+    /// -1 is only the left speaker, 1 only the right
+    #[serde(default)]
+    pub pan: f32,
+    /// a name like "dialogue" or "music", for ducking rules to refer to
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub duck: Option<Ducking>,
+}
+
+fn envelope<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<VolumeKey>, D::Error> {
+    let mut keys = Vec::<VolumeKey>::deserialize(deserializer)?;
+    keys.sort_by_key(|key| key.frame);
+    Ok(keys)
+}
+
+#[derive(Deserialize)]
+pub struct VolumeKey {
+    /// frame of the video
+    pub frame: usize,
+    pub volume: f32,
+}
+
+/// Lower a sound while any sound of another group is playing, like music under dialogue
+#[derive(Deserialize)]
+pub struct Ducking {
+    /// the group that this sound makes way for
+    pub group: String,
+    /// how much quieter the sound gets, in decibels
+    pub db: f32,
+    /// frames to get quieter over before the group starts, and louder again after it stops
+    #[serde(default)]
+    pub fade: usize,
 }
 
 #[derive(Deserialize)]
@@ -178,16 +590,103 @@ pub struct Camera {
     pub zoom: f32,
 }
 
+#[derive(Deserialize)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+
+/// Gradient positions are in the same coordinates as the things in the frame. The radius of a
+/// radial gradient is measured in units of the y axis, so it stays round at any aspect ratio.
+#[derive(Deserialize)]
+pub enum Gradient {
+    Linear {
+        from: Point,
+        to: Point,
+        stops: Vec<GradientStop>,
+    },
+    Radial {
+        center: Point,
+        radius: f32,
+        stops: Vec<GradientStop>,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum Fit {
+    /// fill the frame, cropping the image if the aspect ratios don't match
+    Cover,
+    /// show the whole image, leaving bars if the aspect ratios don't match
+    Contain,
+    Stretch,
+    /// repeat the image at its own resolution (times `scale`)
+    Tile,
+}
+
+#[derive(Deserialize)]
+pub struct BackgroundImage {
+    pub id: u32,
+    pub fit: Fit,
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// shown wherever the image doesn't cover the frame, transparent if missing
+    #[serde(default)]
+    pub fill: Option<Color>,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub enum Background {
+    Color(Color),
+    Gradient(Gradient),
+    Image(BackgroundImage),
+    /// Only for the preview and onion skins. Exported videos are yuv420p H.264, which has no
+    /// alpha, so `export` refuses frames with a transparent background.
+    Transparent,
+}
+
+impl Background {
+    /// The color the frame gets cleared with before anything is drawn
+    pub fn clear_color(&self) -> wgpu::Color {
+        match self {
+            Background::Color(color) => color.to_wgpu_color(),
+            Background::Image(BackgroundImage {
+                fill: Some(color), ..
+            }) => color.to_wgpu_color(),
+            Background::Gradient(_) => wgpu::Color::BLACK,
+            Background::Image(_) | Background::Transparent => wgpu::Color::TRANSPARENT,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Settings {
-    #[serde(default = "bg")]
-    pub bg: Color,
+    #[serde(default = "bg", deserialize_with = "background")]
+    pub bg: Background,
     #[serde(default = "camera")]
     pub camera: Camera,
 }
 
-fn bg() -> Color {
-    Color::new(0, 0, 0)
+fn bg() -> Background {
+    Background::Color(Color::new(0, 0, 0))
+}
+
+/// `bg` used to only be a color, so a bare color is still accepted
+fn background<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Background, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BackgroundOrColor {
+        Background(Background),
+        Color(Color),
+    }
+
+    Ok(match BackgroundOrColor::deserialize(deserializer)? {
+        BackgroundOrColor::Background(background) => background,
+        BackgroundOrColor::Color(color) => Background::Color(color),
+    })
 }
 
 fn camera() -> Camera {
@@ -204,9 +703,223 @@ pub struct FrameDescription {
 }
 
 
+/// A reusable animation (a looping background, an animated logo, ...), drawn into frames
+/// with `Object::Precomp`
+#[derive(Deserialize)]
+pub struct Composition {
+    /// the things in each of the composition's frames
+    pub frames: Vec<Vec<Node>>,
+}
+
+pub type Compositions = HashMap<String, Composition>;
+
+/// What a precomp shows outside of its composition's frames
+#[derive(Deserialize)]
+pub enum TimeMode {
+    /// start over from the first frame
+    Loop,
+    /// keep showing the first frame before the composition starts, and the last one after
+    Hold,
+    /// show nothing
+    Once,
+}
+
+/// A composition drawn as part of a frame. Like everything else it's placed by the node
+/// it's in, and all of its objects are drawn together at that node's depth.
+#[derive(Deserialize)]
+pub struct Precomp {
+    /// name of the composition, in `VideoDescription::compositions`
+    pub composition: String,
+    /// the frame of the video (or of the composition this precomp is in) the composition
+    /// starts at
+    #[serde(default)]
+    pub start: f32,
+    /// frames of the composition per frame of the video
+    #[serde(default = "one")]
+    pub rate: f32,
+    #[serde(default = "time_mode")]
+    pub mode: TimeMode,
+    /// play the composition backwards, from its last frame
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+fn time_mode() -> TimeMode {
+    TimeMode::Loop
+}
+
+impl Precomp {
+    /// Which frame of a composition `frames` long is shown at `frame` of the video (or of the
+    /// composition this precomp is in), if any
+    pub fn composition_frame(&self, frame: usize, frames: usize) -> Option<usize> {
+        if frames == 0 {
+            return None;
+        }
+        let t = ((frame as f32 - self.start) * self.rate).floor();
+        let last = (frames - 1) as f32;
+        let index = match self.mode {
+            TimeMode::Loop => t.rem_euclid(frames as f32),
+            TimeMode::Hold => t.clamp(0.0, last),
+            TimeMode::Once if (0.0..=last).contains(&t) => t,
+            TimeMode::Once => return None,
+        } as usize;
+        Some(if self.reverse {
+            frames - 1 - index
+        } else {
+            index
+        })
+    }
+}
+
 #[derive(Deserialize)]
 pub struct VideoDescription {
-    pub frames : Vec<FrameDescription>,
-    pub sounds : Vec<AudioDescription>,
-    pub fps : usize
+    pub frames: Vec<FrameDescription>,
+    pub sounds: Vec<AudioDescription>,
+    pub fps: usize,
+    #[serde(default)]
+    pub compositions: Compositions,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum ColorMatrix {
+    Bt709,
+    Bt601,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum ColorRange {
+    /// "TV" range, what players and YouTube assume if nothing else is said
+    Limited,
+    /// "PC" range, uses all the code values
+    Full,
+}
+
+/// How the exported video gets encoded.
+#[derive(Deserialize)]
+pub struct ExportSettings {
+    #[serde(default = "matrix")]
+    pub matrix: ColorMatrix,
+    #[serde(default = "range")]
+    pub range: ColorRange,
+    #[serde(default)]
+    pub ten_bit: bool,
+}
+
+fn matrix() -> ColorMatrix {
+    ColorMatrix::Bt709
+}
+
+fn range() -> ColorRange {
+    ColorRange::Limited
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            matrix: matrix(),
+            range: range(),
+            ten_bit: false,
+        }
+    }
+}
+
+/// Safe areas as a fraction of the frame's width and height
+#[derive(Deserialize)]
+pub struct SafeAreas {
+    #[serde(default = "action_safe")]
+    pub action: f32,
+    #[serde(default = "title_safe")]
+    pub title: f32,
+}
+
+fn action_safe() -> f32 {
+    0.93
+}
+
+fn title_safe() -> f32 {
+    0.9
+}
+
+#[derive(Deserialize)]
+pub enum Grid {
+    Thirds,
+    Custom { columns: u32, rows: u32 },
+}
+
+/// Guides drawn over the preview window. None of these ever make it into a render or export.
+#[derive(Deserialize, Default)]
+pub struct Overlays {
+    #[serde(default)]
+    pub safe_areas: Option<SafeAreas>,
+    #[serde(default)]
+    pub grid: Option<Grid>,
+    /// frame number and timecode in the corner of the frame
+    #[serde(default)]
+    pub hud: bool,
+    /// show the transparent parts of the frame as a checkerboard instead of the window behind
+    #[serde(default)]
+    pub checkerboard: bool,
+}
+
+/// What's under a point of the preview window
+#[derive(Serialize)]
+pub struct Inspection {
+    /// indices into `things`, then into `children`, down to the topmost object at the point
+    pub path: Option<Vec<usize>>,
+    /// bounding box of that object, in frame coordinates (-1 to 1 on both axes)
+    pub bounds: Option<Rect>,
+    /// the rendered pixel, as rgba
+    pub color: Option<[u8; 4]>,
+}
+
+/// Where a node or object of a frame is
+#[derive(Serialize)]
+pub struct Bounds {
+    /// indices into `things`, then into `children`
+    pub path: Vec<usize>,
+    /// in frame coordinates (-1 to 1 on both axes)
+    pub bounds: Rect,
+}
+
+/// How finely a waveform is measured. Buckets never straddle two video frames.
+#[derive(Deserialize, Clone, Copy)]
+pub enum WaveformDetail {
+    /// about this many buckets over the whole sound, rounded to a whole number per frame
+    Buckets(usize),
+    BucketsPerFrame(usize),
+}
+
+/// The loudest and quietest samples of each stretch of a sound, for drawing it on a
+/// timeline. Bucket `i` is in frame `i / buckets_per_frame`, counting from the sound's start.
+#[derive(Serialize, Clone)]
+pub struct Waveform {
+    pub buckets_per_frame: usize,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+/// How to turn a line of dialogue into mouth shapes, see `lip_sync`
+#[derive(Deserialize)]
+pub struct LipSync {
+    /// mouth image ids, from closed to open the widest
+    pub mouths: Vec<u32>,
+    /// a mouth for hissing sounds like "s", "f" and "ee", that are loud but hardly open
+    #[serde(default)]
+    pub narrow: Option<u32>,
+    /// frames either side to average loudness over, to stop the mouth flapping
+    #[serde(default)]
+    pub smoothing: usize,
+    /// the fewest frames a mouth is shown for before it can change
+    #[serde(default)]
+    pub hold: usize,
+}
+
+/// Timing markers found in a piece of music, in frames from the start of the sound
+#[derive(Serialize)]
+pub struct Beats {
+    /// beats per minute, 0 if no steady beat was found
+    pub tempo: f32,
+    pub beats: Vec<usize>,
+    /// where notes and hits start, whether they're on the beat or not
+    pub onsets: Vec<usize>,
 }
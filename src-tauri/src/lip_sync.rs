@@ -0,0 +1,108 @@
+/// Mouth shapes for a line of dialogue, worked out from how loud it is in each video frame,
+/// and from how high it sounds to catch hissing sounds, which are loud with the mouth almost
+/// shut. It's no match for keying it by hand, but makes a good first pass.
+use crate::audio::{Audio, SAMPLE_RATE};
+use crate::interface::LipSync;
+use crate::mixer::frame_to_sample;
+use crate::spectrum::{mono, Spectrum};
+
+/// Frames this much quieter than the loudest one, in decibels, get a closed mouth
+const SILENCE_DB: f32 = -30.0;
+/// Sound mostly above this frequency, in Hz, is hissing
+const HISS_FREQUENCY: f32 = 4000.0;
+
+/// The mouth image id to show for each frame of `audio`, counting from its start, at `fps`
+pub fn lip_sync(audio: &Audio, fps: usize, settings: &LipSync) -> Vec<u32> {
+    let fps = fps.max(1);
+    let samples = mono(&audio.samples);
+    let frames = (samples.len() * fps).div_ceil(SAMPLE_RATE as usize);
+    let slice = |frame: usize| {
+        let start = frame_to_sample(frame, fps).min(samples.len());
+        let end = frame_to_sample(frame + 1, fps).min(samples.len());
+        &samples[start..end]
+    };
+
+    let loudness: Vec<f32> = (0..frames).map(|frame| rms(slice(frame))).collect();
+    let loudest = loudness.iter().copied().fold(0.0, f32::max);
+    // how open the mouth is in each frame, from 0 to 1
+    let openness: Vec<f32> = loudness
+        .iter()
+        .map(|&rms| {
+            if rms == 0.0 {
+                return 0.0;
+            }
+            let db = 20.0 * (rms / loudest).log10();
+            (1.0 - db / SILENCE_DB).clamp(0.0, 1.0)
+        })
+        .collect();
+    let openness = smooth(&openness, settings.smoothing);
+
+    let spectrum = Spectrum::new(frame_to_sample(1, fps));
+    let shapes: Vec<u32> = openness
+        .iter()
+        .enumerate()
+        .map(|(frame, &open)| {
+            let mouths = &settings.mouths;
+            if let Some(narrow) = settings.narrow.filter(|_| open > 0.0) {
+                if centroid(&spectrum, slice(frame)) > HISS_FREQUENCY {
+                    return narrow;
+                }
+            }
+            // the first mouth is closed, and the rest share out the rest of the loudness
+            let index = (open * (mouths.len() - 1) as f32).ceil() as usize;
+            mouths[index.min(mouths.len() - 1)]
+        })
+        .collect();
+    hold(shapes, settings.hold)
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// The average frequency of `samples`, weighted by how loud each one is
+fn centroid(spectrum: &Spectrum, samples: &[f32]) -> f32 {
+    let magnitudes = spectrum.magnitudes(samples);
+    let total: f32 = magnitudes.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let weighted: f32 = magnitudes
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| spectrum.frequency(bin) * magnitude)
+        .sum();
+    weighted / total
+}
+
+/// Average each value with the `radius` values either side of it
+fn smooth(values: &[f32], radius: usize) -> Vec<f32> {
+    if radius == 0 {
+        return values.to_vec();
+    }
+    (0..values.len())
+        .map(|i| {
+            let window = &values[i.saturating_sub(radius)..(i + radius + 1).min(values.len())];
+            window.iter().sum::<f32>() / window.len() as f32
+        })
+        .collect()
+}
+
+/// Keep each mouth for at least `frames` frames
+fn hold(mut shapes: Vec<u32>, frames: usize) -> Vec<u32> {
+    let mut since_change = 0;
+    for i in 1..shapes.len() {
+        since_change += 1;
+        if shapes[i] != shapes[i - 1] {
+            if since_change < frames {
+                shapes[i] = shapes[i - 1];
+            } else {
+                since_change = 0;
+            }
+        }
+    }
+    shapes
+}
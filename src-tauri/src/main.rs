@@ -2,22 +2,49 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use request_handlers::{
-    export, next_frame, pause, play, prev_frame, reverse, stop, to_base64_png,
-    update_media_resources, update_video_description,
+    beats, bounds, export, export_audio, import_psd, import_svg, inspect, lip_sync, next_frame,
+    onion_skin, overlays, pause, play, prev_frame, reverse, stop, to_base64_png,
+    update_media_resources, update_video_description, waveform,
 };
 
-use std::{sync::mpsc, thread};
+use std::{env, path::PathBuf, process, sync::mpsc, thread};
 
+mod aseprite;
+mod audio;
+mod beats;
 mod interface;
+mod lip_sync;
+mod mixer;
+mod node_json;
+mod psd_import;
+mod reader;
 mod renderer;
 mod request_handlers;
+mod resources;
 mod signals;
+mod spectrum;
+mod svg_import;
+mod video_clip;
+mod waveform;
 
 /// There are 2 main parts of this app, the wgpu renderer and the tauri applicaton.
 ///
 /// The tauri application is responsible for handling all the user interaction,
 /// The renderer is responsible for rendering the resulting video
+///
+/// `booglanim --golden <dir> [--bless]` skips both and runs the golden image tests instead.
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--golden") {
+        let fixtures_dir = PathBuf::from(args.get(i + 1).expect("--golden needs a directory"));
+        let bless = args.iter().any(|arg| arg == "--bless");
+        if let Err(e) = renderer::golden::run_and_report(&fixtures_dir, bless) {
+            eprintln!("{:?}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
     video_rs::init().expect("failed to initialize video-rs");
     let (signal_tx, signal_rx) = mpsc::channel();
     {
@@ -29,12 +56,22 @@ fn main() {
                     update_media_resources,
                     to_base64_png,
                     export,
+                    export_audio,
                     play,
                     pause,
                     stop,
                     next_frame,
                     prev_frame,
-                    reverse
+                    reverse,
+                    onion_skin,
+                    overlays,
+                    inspect,
+                    bounds,
+                    waveform,
+                    lip_sync,
+                    beats,
+                    import_svg,
+                    import_psd
                 ])
                 .any_thread()
                 .run(tauri::generate_context!())
@@ -0,0 +1,260 @@
+/// Mixing the sounds of a video into one stereo track. Sounds are placed by video frame, so
+/// they stay in sync with the animation whatever its frame rate.
+use anyhow::{Context, Result};
+
+use std::path::Path;
+
+use crate::audio::SAMPLE_RATE;
+use crate::interface::{AudioDescription, Ducking, VolumeKey};
+use crate::signals::MediaResources;
+
+/// The sample frame `frame` of a video playing at `fps` starts at
+pub fn frame_to_sample(frame: usize, fps: usize) -> usize {
+    frame * SAMPLE_RATE as usize / fps
+}
+
+/// Mix `sounds` into `out`, which starts `start` samples into the video. Sounds play from
+/// their start frame until their end frame, or until they run out if they don't loop, with
+/// their volume, fades, envelope, pan and ducking applied. Samples are clipped to -1..1.
+pub fn mix(
+    out: &mut [[f32; 2]],
+    start: usize,
+    sounds: &[AudioDescription],
+    resources: &MediaResources,
+    fps: usize,
+) {
+    out.fill([0.0, 0.0]);
+    let spans: Vec<Option<(usize, usize)>> = sounds
+        .iter()
+        .map(|sound| {
+            let len = resources.sounds.get(&sound.id)?.samples.len();
+            (len > 0).then(|| span(sound, len, fps))
+        })
+        .collect();
+    for (sound, span) in sounds.iter().zip(&spans) {
+        let (Some((sound_start, sound_end)), Some(audio)) =
+            (*span, resources.sounds.get(&sound.id))
+        else {
+            continue;
+        };
+        let len = audio.samples.len();
+        // when the sounds this one makes way for are playing
+        let ducked_by: Vec<(usize, usize)> = match &sound.duck {
+            Some(duck) => sounds
+                .iter()
+                .zip(&spans)
+                .filter(|(other, _)| other.group.as_ref() == Some(&duck.group))
+                .filter_map(|(_, span)| *span)
+                .collect(),
+            None => vec![],
+        };
+        let [pan_left, pan_right] = pan(sound.pan);
+
+        let from = start.max(sound_start);
+        let to = (start + out.len()).min(sound_end);
+        for t in from..to {
+            let mut gain = sound.volume * envelope(&sound.envelope, t, fps);
+            gain *= fade(sound, t, (sound_start, sound_end), fps);
+            if let Some(duck) = &sound.duck {
+                gain *= ducking(duck, t, &ducked_by, fps);
+            }
+            let [left, right] = audio.samples[(t - sound_start) % len];
+            let mixed = &mut out[t - start];
+            mixed[0] += left * gain * pan_left;
+            mixed[1] += right * gain * pan_right;
+        }
+    }
+    for sample in out {
+        *sample = sample.map(|s| s.clamp(-1.0, 1.0));
+    }
+}
+
+/// The samples of the video `sound` plays between, if it's `len` samples long
+fn span(sound: &AudioDescription, len: usize, fps: usize) -> (usize, usize) {
+    let sound_start = frame_to_sample(sound.start, fps);
+    let end = sound.end.map(|end| frame_to_sample(end, fps));
+    let sound_end = match (end, sound.looping) {
+        (Some(end), true) => end,
+        (Some(end), false) => end.min(sound_start + len),
+        (None, true) => usize::MAX,
+        (None, false) => sound_start + len,
+    };
+    (sound_start, sound_end)
+}
+
+/// The gains of the left and right channels. The middle leaves both alone, and panning only
+/// turns the other side down, so nothing gets louder.
+fn pan(pan: f32) -> [f32; 2] {
+    let pan = pan.clamp(-1.0, 1.0);
+    [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)]
+}
+
+/// The envelope's volume at sample `t` of the video
+fn envelope(keys: &[VolumeKey], t: usize, fps: usize) -> f32 {
+    let frame = t as f32 * fps as f32 / SAMPLE_RATE as f32;
+    let next = keys.partition_point(|key| key.frame as f32 <= frame);
+    match (next.checked_sub(1).map(|i| &keys[i]), keys.get(next)) {
+        (None, None) => 1.0,
+        (Some(key), None) | (None, Some(key)) => key.volume,
+        (Some(a), Some(b)) => {
+            let progress = (frame - a.frame as f32) / (b.frame as f32 - a.frame as f32);
+            a.volume + (b.volume - a.volume) * progress
+        }
+    }
+}
+
+/// The fade in and out at sample `t` of the video, for a sound playing between `start` and
+/// `end`
+fn fade(sound: &AudioDescription, t: usize, (start, end): (usize, usize), fps: usize) -> f32 {
+    let mut gain = 1.0;
+    let fade_in = frame_to_sample(sound.fade_in, fps);
+    if fade_in > 0 {
+        gain *= ((t - start) as f32 / fade_in as f32).min(1.0);
+    }
+    let fade_out = frame_to_sample(sound.fade_out, fps);
+    if fade_out > 0 && end != usize::MAX {
+        gain *= ((end - t) as f32 / fade_out as f32).min(1.0);
+    }
+    gain
+}
+
+/// How much `duck` turns a sound down at sample `t` of the video, with the sounds it makes
+/// way for playing in `spans`
+fn ducking(duck: &Ducking, t: usize, spans: &[(usize, usize)], fps: usize) -> f32 {
+    // samples to the nearest of them
+    let Some(distance) = spans
+        .iter()
+        .map(|&(start, end)| {
+            if t < start {
+                start - t
+            } else if t >= end {
+                t + 1 - end
+            } else {
+                0
+            }
+        })
+        .min()
+    else {
+        return 1.0;
+    };
+    let fade = frame_to_sample(duck.fade, fps);
+    let amount = if fade == 0 {
+        (distance == 0) as u8 as f32
+    } else {
+        (1.0 - distance as f32 / fade as f32).max(0.0)
+    };
+    10f32.powf(-duck.db.abs() * amount / 20.0)
+}
+
+/// All of a video's sound, `frames` frames long
+pub fn mixdown(
+    sounds: &[AudioDescription],
+    resources: &MediaResources,
+    fps: usize,
+    frames: usize,
+) -> Vec<[f32; 2]> {
+    let mut track = vec![[0.0, 0.0]; frame_to_sample(frames, fps)];
+    mix(&mut track, 0, sounds, resources, fps);
+    track
+}
+
+/// Save a video's sound on its own, as a 16 bit wav file
+pub fn export_wav(
+    sounds: &[AudioDescription],
+    resources: &MediaResources,
+    fps: usize,
+    frames: usize,
+    path: &Path,
+) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("can't create {}", path.display()))?;
+    for sample in mixdown(sounds, resources, fps, frames) {
+        for channel in sample {
+            writer.write_sample((channel * i16::MAX as f32) as i16)?;
+        }
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sound(json: serde_json::Value) -> AudioDescription {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn envelope_goes_between_keys_in_frame_order() {
+        // sent out of order, read back sorted
+        let sound = sound(json!({
+            "id": 0,
+            "start": 0,
+            "envelope": [{"frame": 2, "volume": 0.0}, {"frame": 1, "volume": 1.0}]
+        }));
+        let at = |frame: f32| envelope(&sound.envelope, (frame * 2000.0) as usize, 24);
+        assert!(close(at(0.0), 1.0));
+        assert!(close(at(1.5), 0.5));
+        assert!(close(at(3.0), 0.0));
+        assert!(close(envelope(&[], 100, 24), 1.0));
+    }
+
+    #[test]
+    fn fades_at_both_ends() {
+        let sound = sound(json!({"id": 0, "start": 0, "fade_in": 1, "fade_out": 2}));
+        let span = (0, 10_000);
+        assert!(close(fade(&sound, 0, span, 24), 0.0));
+        assert!(close(fade(&sound, 1000, span, 24), 0.5));
+        assert!(close(fade(&sound, 5000, span, 24), 1.0));
+        assert!(close(fade(&sound, 8000, span, 24), 0.5));
+        // looping forever, so it never fades out
+        assert!(close(fade(&sound, 8000, (0, usize::MAX), 24), 1.0));
+    }
+
+    #[test]
+    fn panning_only_turns_the_other_side_down() {
+        assert_eq!(pan(0.0), [1.0, 1.0]);
+        assert_eq!(pan(0.5), [0.5, 1.0]);
+        assert_eq!(pan(-1.0), [1.0, 0.0]);
+        assert_eq!(pan(3.0), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn ducking_fades_around_the_other_sounds() {
+        let duck = Ducking {
+            group: "dialogue".to_string(),
+            db: -20.0,
+            fade: 1,
+        };
+        let spans = [(4000, 6000)];
+        assert!(close(ducking(&duck, 5000, &spans, 24), 0.1));
+        assert!(close(ducking(&duck, 1000, &spans, 24), 1.0));
+        // halfway through fading down
+        assert!(close(ducking(&duck, 3000, &spans, 24), 10f32.powf(-0.5)));
+        assert!(close(ducking(&duck, 5000, &[], 24), 1.0));
+    }
+
+    #[test]
+    fn sounds_play_until_their_end_or_running_out() {
+        let once = sound(json!({"id": 0, "start": 1}));
+        assert_eq!(span(&once, 500, 24), (2000, 2500));
+        let looping = sound(json!({"id": 0, "start": 1, "looping": true}));
+        assert_eq!(span(&looping, 500, 24), (2000, usize::MAX));
+        let ending = sound(json!({"id": 0, "start": 1, "end": 3, "looping": true}));
+        assert_eq!(span(&ending, 500, 24), (2000, 6000));
+        let short = sound(json!({"id": 0, "start": 1, "end": 3}));
+        assert_eq!(span(&short, 500, 24), (2000, 2500));
+    }
+}
@@ -0,0 +1,114 @@
+/// Node trees built by the importers (`svg_import`, `psd_import`), as JSON in the format
+/// `interface::Node` is read from, so the UI can drop them into a frame and animate them.
+/// Documents are in pixels with y going down, and are placed to fill the height of the frame.
+use anyhow::{anyhow, Context, Result};
+use image::DynamicImage;
+use serde_json::{json, Value};
+
+use std::{fs, path::Path};
+
+/// The aspect ratio of the video a document is put in, when the UI doesn't say
+pub const DEFAULT_ASPECT_RATIO: f32 = 16.0 / 9.0;
+
+/// A node, named after the part of the document it came from. The renderer ignores the name.
+pub fn node(name: &str, z: f32, transform: Value, children: Vec<Value>) -> Value {
+    json!({
+        "Node": {
+            "name": name,
+            "z": z,
+            "transform": transform,
+            "visible": true,
+            "children": children,
+        }
+    })
+}
+
+pub fn point(x: f32, y: f32) -> Value {
+    json!({ "x": x, "y": y })
+}
+
+pub fn identity() -> Value {
+    json!({ "pos": point(0.0, 0.0), "scale": point(1.0, 1.0), "angle": 0.0 })
+}
+
+/// The transform of a document's root node, turning its pixels into frame units (from -1 to
+/// 1 with y going up) with the document centered
+pub fn document_transform(width: f32, height: f32, aspect_ratio: f32) -> Value {
+    let scale = 2.0 / height;
+    json!({
+        "pos": point(-width / 2.0 * scale / aspect_ratio, height / 2.0 * scale),
+        "scale": point(scale / aspect_ratio, -scale),
+        "angle": 0.0,
+    })
+}
+
+/// The transform that makes an `Img` of `image` cover the box at (`x`, `y`) of size `w`x`h`
+/// in document pixels
+pub fn image_transform(
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    image: &DynamicImage,
+    aspect_ratio: f32,
+) -> Value {
+    // an `Img` of scale 1 is 1 wide and as high as its aspect ratio makes it in the frame,
+    // and upside down here, because of the flip at the root
+    let img_aspect_ratio = image.height() as f32 / image.width() as f32;
+    json!({
+        "pos": point(x + w / 2.0, y + h / 2.0),
+        "scale": point(w, -h / (img_aspect_ratio * aspect_ratio)),
+        "angle": 0.0,
+    })
+}
+
+/// Where the images taken out of the document at `path` go: a directory next to it, named
+/// after it and `suffix`
+pub fn images_dir(path: &str, suffix: &str) -> Result<String> {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .ok_or(anyhow!("{} isn't a file", path.display()))?
+        .to_string_lossy();
+    let dir = match path.parent() {
+        Some(parent) => parent.join(format!("{}_{}", stem, suffix)),
+        None => format!("{}_{}", stem, suffix).into(),
+    };
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+/// Save `image` as `<dir>/<index>.png`, where `dir` is relative to `root`, and return its
+/// path relative to `root`
+pub fn save_image(root: &Path, dir: &str, index: usize, image: &DynamicImage) -> Result<String> {
+    let path = format!("{}/{}.png", dir, index);
+    let full_path = root.join(&path);
+    if let Some(dir) = full_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    image
+        .save(&full_path)
+        .with_context(|| format!("can't save {}", full_path.display()))?;
+    Ok(path)
+}
+
+/// An imported tree read back the way the renderer reads it, panicking if it isn't a node
+#[cfg(test)]
+pub fn read_back(tree: Value) -> crate::interface::Node {
+    match serde_json::from_value(tree).unwrap() {
+        crate::interface::Container::Node(node) => node,
+        crate::interface::Container::Leaf(_) => panic!("the document isn't a node"),
+    }
+}
+
+/// The children of an imported node, panicking if any of them isn't a node: importers put
+/// every object in a node of its own
+#[cfg(test)]
+pub fn child_nodes(node: &crate::interface::Node) -> Vec<&crate::interface::Node> {
+    node.children
+        .iter()
+        .map(|child| match child {
+            crate::interface::Container::Node(node) => node,
+            crate::interface::Container::Leaf(_) => panic!("an object isn't in a node"),
+        })
+        .collect()
+}
@@ -0,0 +1,533 @@
+/// Splitting Photoshop (`.psd`/`.psb`) documents into their layers, following
+/// https://www.adobe.com/devnet-apps/photoshop/fileformatashtml/. Every visible layer is saved
+/// as an image next to the document, and comes back in a node tree matching the document's
+/// groups, so a painting can be split into planes or parts and animated.
+///
+/// Only 8 bit RGB and grayscale documents are read. Layer masks, effects and adjustment
+/// layers are ignored, and the renderer draws every layer with normal blending: the blend
+/// modes are only passed on, for the UI to show.
+use anyhow::{anyhow, Context, Result};
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use std::{fs, io::Read, path::Path};
+
+use crate::node_json::{
+    document_transform, identity, image_transform, images_dir, node, save_image,
+    DEFAULT_ASPECT_RATIO,
+};
+use crate::reader::{Endian, Reader};
+
+const RGB_MODE: u16 = 3;
+const GRAYSCALE_MODE: u16 = 1;
+
+/// layer flag set when the layer is hidden
+const HIDDEN: u8 = 2;
+
+/// Section divider types, marking where groups start and end
+const OPEN_FOLDER: u32 = 1;
+const CLOSED_FOLDER: u32 = 2;
+const GROUP_END: u32 = 3;
+
+#[derive(Serialize)]
+pub struct PsdImport {
+    /// The whole document as a `Node`, filling the height of the frame. Every node has the
+    /// name of the layer or group it came from as its `name`, which the renderer ignores.
+    pub node: Value,
+    /// Every visible layer, bottom first. The `Img`s in `node` use their index in this list as
+    /// their id, to be swapped for the ids they get as resources.
+    pub layers: Vec<PsdLayer>,
+}
+
+#[derive(Serialize)]
+pub struct PsdLayer {
+    /// where the layer was saved, relative to the same directory as the document's path
+    pub path: String,
+    pub name: String,
+    /// the layer's opacity, times that of the groups it's in, also set on its `Img`
+    pub opacity: f32,
+    /// how the layer blends with what's below it in Photoshop, like "normal" or "multiply"
+    pub blend_mode: String,
+}
+
+/// A length that's 8 bytes long in `large` (psb) documents
+fn length(r: &mut Reader, large: bool) -> Result<usize> {
+    if large {
+        Ok(r.u64()? as usize)
+    } else {
+        Ok(r.u32()? as usize)
+    }
+}
+
+struct LayerRecord {
+    name: String,
+    top: i32,
+    left: i32,
+    width: u32,
+    height: u32,
+    /// channel ids (0 to 2 for colors, -1 for alpha) and the length of their data
+    channels: Vec<(i16, usize)>,
+    blend_mode: [u8; 4],
+    opacity: u8,
+    hidden: bool,
+    divider: Option<u32>,
+}
+
+/// A layer, or a group holding more, by index in the layer records
+enum Item {
+    Layer(usize),
+    Group(usize, Vec<Item>),
+}
+
+/// The layers and groups of a document, from the bottom up
+fn items(records: &[LayerRecord]) -> Vec<Item> {
+    // records go from the bottom of the layer stack to the top, and groups are read from
+    // their (hidden) end marker at the bottom up to their folder at the top
+    let mut groups: Vec<Vec<Item>> = vec![vec![]];
+    for (i, record) in records.iter().enumerate() {
+        match record.divider {
+            Some(GROUP_END) => groups.push(vec![]),
+            Some(OPEN_FOLDER | CLOSED_FOLDER) if groups.len() > 1 => {
+                let children = groups.pop().unwrap();
+                groups.last_mut().unwrap().push(Item::Group(i, children));
+            }
+            _ => groups.last_mut().unwrap().push(Item::Layer(i)),
+        }
+    }
+    // unclosed groups are just put in the document
+    groups.into_iter().flatten().collect()
+}
+
+/// Read the psd at `path`, relative to `root`, saving its layers next to it, for a video of
+/// the given aspect ratio
+pub fn import_psd(root: &Path, path: &str, aspect_ratio: Option<f32>) -> Result<PsdImport> {
+    let full_path = root.join(path);
+    let data = fs::read(&full_path).with_context(|| format!("can't open {}", path))?;
+    let (width, height, records, images) =
+        parse(&data).with_context(|| format!("can't read psd {}", path))?;
+    let items = items(&records);
+
+    let mut importer = Importer {
+        root,
+        layers_dir: images_dir(path, "layers")?,
+        records: &records,
+        images,
+        layers: vec![],
+        count: 0,
+        aspect_ratio: aspect_ratio.unwrap_or(DEFAULT_ASPECT_RATIO),
+    };
+    let children = importer.nodes(&items, 1.0, 0)?;
+    let name = full_path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let transform = document_transform(width as f32, height as f32, importer.aspect_ratio);
+    Ok(PsdImport {
+        node: node(&name, 0.0, transform, children),
+        layers: importer.layers,
+    })
+}
+
+struct Importer<'a> {
+    /// where layers are saved, and what their paths are relative to
+    root: &'a Path,
+    layers_dir: String,
+    records: &'a [LayerRecord],
+    images: Vec<Option<DynamicImage>>,
+    layers: Vec<PsdLayer>,
+    /// how many layers and groups have been placed, to give each a higher z than the last
+    count: usize,
+    aspect_ratio: f32,
+}
+
+impl Importer<'_> {
+    /// Nodes for the visible layers and groups in `items`, inside groups with the given
+    /// opacity, with z values that keep them drawn in the document's order
+    fn nodes(&mut self, items: &[Item], opacity: f32, parent_z: usize) -> Result<Vec<Value>> {
+        let mut nodes = vec![];
+        for item in items {
+            let (Item::Layer(i) | Item::Group(i, _)) = *item;
+            let records = self.records;
+            let record = &records[i];
+            if record.hidden {
+                continue;
+            }
+            let opacity = opacity * record.opacity as f32 / 255.0;
+            self.count += 1;
+            let z = self.count;
+            let relative_z = (z - parent_z) as f32;
+            match item {
+                Item::Group(_, children) => {
+                    let children = self.nodes(children, opacity, z)?;
+                    nodes.push(node(&record.name, relative_z, identity(), children));
+                }
+                Item::Layer(_) => {
+                    let Some(image) = self.images[i].take() else {
+                        continue;
+                    };
+                    let id = self.layers.len();
+                    self.layers.push(PsdLayer {
+                        path: save_image(self.root, &self.layers_dir, id, &image)?,
+                        name: record.name.clone(),
+                        opacity,
+                        blend_mode: blend_mode_name(&record.blend_mode),
+                    });
+                    let transform = image_transform(
+                        record.left as f32,
+                        record.top as f32,
+                        record.width as f32,
+                        record.height as f32,
+                        &image,
+                        self.aspect_ratio,
+                    );
+                    let leaf = json!({
+                        "Leaf": { "Img": { "id": id, "subrect": null, "opacity": opacity } }
+                    });
+                    nodes.push(node(&record.name, relative_z, transform, vec![leaf]));
+                }
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+fn blend_mode_name(key: &[u8; 4]) -> String {
+    let name = match key {
+        b"pass" => "pass through",
+        b"norm" => "normal",
+        b"diss" => "dissolve",
+        b"dark" => "darken",
+        b"mul " => "multiply",
+        b"idiv" => "color burn",
+        b"lbrn" => "linear burn",
+        b"dkCl" => "darker color",
+        b"lite" => "lighten",
+        b"scrn" => "screen",
+        b"div " => "color dodge",
+        b"lddg" => "linear dodge",
+        b"lgCl" => "lighter color",
+        b"over" => "overlay",
+        b"sLit" => "soft light",
+        b"hLit" => "hard light",
+        b"vLit" => "vivid light",
+        b"lLit" => "linear light",
+        b"pLit" => "pin light",
+        b"hMix" => "hard mix",
+        b"diff" => "difference",
+        b"smud" => "exclusion",
+        b"fsub" => "subtract",
+        b"fdiv" => "divide",
+        b"hue " => "hue",
+        b"sat " => "saturation",
+        b"colr" => "color",
+        b"lum " => "luminosity",
+        _ => return String::from_utf8_lossy(key).trim().to_string(),
+    };
+    name.to_string()
+}
+
+/// The document's size, its layer records, and the image of each one (if it has pixels)
+#[allow(clippy::type_complexity)]
+fn parse(data: &[u8]) -> Result<(u32, u32, Vec<LayerRecord>, Vec<Option<DynamicImage>>)> {
+    let mut r = Reader::new(data, Endian::Big);
+    if r.bytes(4)? != b"8BPS" {
+        return Err(anyhow!("not a photoshop file"));
+    }
+    let large = match r.u16()? {
+        1 => false,
+        2 => true,
+        version => return Err(anyhow!("unknown version {}", version)),
+    };
+    r.bytes(6)?;
+    r.u16()?; // channels of the flattened image
+    let (height, width) = (r.u32()?, r.u32()?);
+    let depth = r.u16()?;
+    let mode = r.u16()?;
+    if depth != 8 {
+        return Err(anyhow!(
+            "only 8 bit documents are supported, not {} bit",
+            depth
+        ));
+    }
+    if mode != RGB_MODE && mode != GRAYSCALE_MODE {
+        return Err(anyhow!("only RGB and grayscale documents are supported"));
+    }
+    r.section()?; // color mode data
+    r.section()?; // image resources
+
+    let layer_and_mask_len = length(&mut r, large)?;
+    if layer_and_mask_len == 0 {
+        return Ok((width, height, vec![], vec![]));
+    }
+    let layer_info_len = length(&mut r, large)?;
+    if layer_info_len == 0 {
+        return Ok((width, height, vec![], vec![]));
+    }
+    // negative when the first alpha channel is the flattened image's transparency
+    let count = r.i16()?.unsigned_abs() as usize;
+
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        records.push(read_layer_record(&mut r, large)?);
+    }
+    let mut images = Vec::with_capacity(count);
+    for record in &records {
+        images.push(read_layer_image(&mut r, record, mode, large)?);
+    }
+    Ok((width, height, records, images))
+}
+
+/// `large` is set for psb documents
+fn read_layer_record(r: &mut Reader, large: bool) -> Result<LayerRecord> {
+    let (top, left, bottom, right) = (r.i32()?, r.i32()?, r.i32()?, r.i32()?);
+    let channel_count = r.u16()?;
+    let mut channels = vec![];
+    for _ in 0..channel_count {
+        channels.push((r.i16()?, length(r, large)?));
+    }
+    if r.bytes(4)? != b"8BIM" {
+        return Err(anyhow!("layer record is corrupt"));
+    }
+    let blend_mode: [u8; 4] = r.bytes(4)?.try_into()?;
+    let opacity = r.byte()?;
+    r.byte()?; // clipping
+    let flags = r.byte()?;
+    r.byte()?;
+
+    let mut extra = Reader::new(r.section()?, Endian::Big);
+    extra.section()?; // mask
+    extra.section()?; // blending ranges
+
+    // pascal string, padded to a multiple of 4 bytes with its length byte
+    let name_len = extra.byte()? as usize;
+    let mut name = String::from_utf8_lossy(extra.bytes(name_len)?).into_owned();
+    extra.bytes((4 - (name_len + 1) % 4) % 4)?;
+
+    let mut divider = None;
+    while extra.rest().len() >= 12 {
+        let signature = extra.bytes(4)?;
+        if signature != b"8BIM" && signature != b"8B64" {
+            break;
+        }
+        let key = extra.bytes(4)?;
+        let len = extra.u32()? as usize;
+        let mut block = extra.sub(len)?;
+        match key {
+            // the name in full, rather than cut down to what the pascal string can hold
+            b"luni" => {
+                let chars = block.u32()? as usize;
+                let utf16 = (0..chars)
+                    .map(|_| block.u16())
+                    .collect::<Result<Vec<_>>>()?;
+                name = String::from_utf16_lossy(&utf16);
+            }
+            b"lsct" | b"lsdk" => divider = Some(block.u32()?),
+            _ => {}
+        }
+    }
+
+    Ok(LayerRecord {
+        name,
+        top,
+        left,
+        width: (right - left).max(0) as u32,
+        height: (bottom - top).max(0) as u32,
+        channels,
+        blend_mode,
+        opacity,
+        hidden: flags & HIDDEN != 0,
+        divider,
+    })
+}
+
+/// Read a layer's channels into an image. Layers without pixels (groups, adjustment layers)
+/// have none.
+fn read_layer_image(
+    r: &mut Reader,
+    record: &LayerRecord,
+    mode: u16,
+    large: bool,
+) -> Result<Option<DynamicImage>> {
+    let (w, h) = (record.width as usize, record.height as usize);
+    let mut image = RgbaImage::from_pixel(record.width, record.height, Rgba([0, 0, 0, 255]));
+    for &(id, len) in &record.channels {
+        let mut channel = r.sub(len)?;
+        // masks have their own size, and aren't used
+        if id < -1 || w * h == 0 {
+            continue;
+        }
+        let pixels = read_channel(&mut channel, w, h, large)?;
+        let targets: &[usize] = match (id, mode) {
+            (-1, _) => &[3],
+            (0, GRAYSCALE_MODE) => &[0, 1, 2],
+            (0..=2, RGB_MODE) => &[id as usize],
+            _ => &[],
+        };
+        for (pixel, value) in image.pixels_mut().zip(pixels) {
+            for &target in targets {
+                pixel[target] = value;
+            }
+        }
+    }
+    if w * h == 0 {
+        return Ok(None);
+    }
+    Ok(Some(DynamicImage::ImageRgba8(image)))
+}
+
+/// Decompress one channel of `w`x`h` pixels
+fn read_channel(r: &mut Reader, w: usize, h: usize, large: bool) -> Result<Vec<u8>> {
+    let compression = r.u16()?;
+    let rest = r.rest();
+    let mut pixels = match compression {
+        0 => rest.to_vec(),
+        1 => {
+            // byte counts of every row, then the rows in PackBits
+            let mut row_lengths = vec![];
+            for _ in 0..h {
+                row_lengths.push(if large {
+                    r.u32()? as usize
+                } else {
+                    r.u16()? as usize
+                });
+            }
+            let mut pixels = Vec::with_capacity(w * h);
+            for len in row_lengths {
+                unpack_bits(r.bytes(len)?, w, &mut pixels);
+            }
+            pixels
+        }
+        2 | 3 => {
+            let mut pixels = Vec::with_capacity(w * h);
+            ZlibDecoder::new(rest).read_to_end(&mut pixels)?;
+            // each byte is stored as the difference from the one to its left
+            if compression == 3 {
+                for row in pixels.chunks_mut(w) {
+                    for x in 1..row.len() {
+                        row[x] = row[x].wrapping_add(row[x - 1]);
+                    }
+                }
+            }
+            pixels
+        }
+        compression => return Err(anyhow!("unknown compression {}", compression)),
+    };
+    pixels.resize(w * h, 0);
+    Ok(pixels)
+}
+
+/// Decompress a PackBits row onto `out`, cut or padded to `w` bytes so that a broken row
+/// doesn't shift the ones after it
+fn unpack_bits(mut data: &[u8], w: usize, out: &mut Vec<u8>) {
+    let end = out.len() + w;
+    while let Some((&header, rest)) = data.split_first() {
+        let header = header as i8;
+        data = rest;
+        if header >= 0 {
+            let n = (header as usize + 1).min(data.len());
+            out.extend_from_slice(&data[..n]);
+            data = &data[n..];
+        } else if header != -128 {
+            let Some((&value, rest)) = data.split_first() else {
+                break;
+            };
+            out.resize(out.len() + (1 - header as isize) as usize, value);
+            data = rest;
+        }
+    }
+    out.resize(end, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::{Container, Object};
+    use crate::node_json::{child_nodes, read_back};
+
+    fn record(name: &str, divider: Option<u32>) -> LayerRecord {
+        LayerRecord {
+            name: name.to_string(),
+            top: 10,
+            left: 20,
+            width: 4,
+            height: 2,
+            channels: vec![],
+            blend_mode: *b"norm",
+            opacity: 128,
+            hidden: false,
+            divider,
+        }
+    }
+
+    #[test]
+    fn folders_become_groups_without_hidden_layers() {
+        let root = std::env::temp_dir().join(format!("psd_groups_{}", std::process::id()));
+        // bottom to top, like in the file
+        let records = [
+            record("</Layer group>", Some(GROUP_END)),
+            record("inside", None),
+            LayerRecord {
+                hidden: true,
+                ..record("hidden", None)
+            },
+            record("group", Some(OPEN_FOLDER)),
+            LayerRecord {
+                opacity: 255,
+                ..record("top", None)
+            },
+        ];
+        let pixels = || Some(DynamicImage::ImageRgba8(RgbaImage::new(4, 2)));
+        let mut importer = Importer {
+            root: &root,
+            layers_dir: "layers".to_string(),
+            records: &records,
+            images: vec![None, pixels(), pixels(), None, pixels()],
+            layers: vec![],
+            count: 0,
+            aspect_ratio: DEFAULT_ASPECT_RATIO,
+        };
+        let children = importer.nodes(&items(&records), 1.0, 0).unwrap();
+        let tree = node("doc", 0.0, document_transform(100.0, 50.0, 1.0), children);
+        fs::remove_dir_all(&root).unwrap();
+
+        let layers = &importer.layers;
+        let names: Vec<_> = layers.iter().map(|layer| &layer.name[..]).collect();
+        assert_eq!(names, ["inside", "top"]);
+        assert!(layers.iter().all(|layer| layer.blend_mode == "normal"));
+
+        let document = read_back(tree);
+        let [group, top] = child_nodes(&document)[..] else {
+            panic!("expected the group and the top layer");
+        };
+        // the group is drawn below the layer above it
+        assert!(group.z < top.z);
+        let [inside] = child_nodes(group)[..] else {
+            panic!("the hidden layer wasn't left out");
+        };
+        let [Container::Leaf(Object::Img(inside_img))] = &inside.children[..] else {
+            panic!("the layer isn't an image");
+        };
+        assert_eq!(inside_img.id, 0);
+        // the layer's opacity times its group's
+        assert!((inside_img.opacity - (128.0 / 255.0_f32).powi(2)).abs() < 1e-6);
+        assert_eq!(inside.transform.pos.x, 22.0);
+        assert_eq!(inside.transform.pos.y, 11.0);
+        let [Container::Leaf(Object::Img(top_img))] = &top.children[..] else {
+            panic!("the layer isn't an image");
+        };
+        assert_eq!((top_img.id, top_img.opacity), (1, 1.0));
+    }
+
+    #[test]
+    fn unpacked_rows_are_as_wide_as_the_layer() {
+        let mut out = vec![];
+        // a literal run of 2, then 3 repeats of 7
+        unpack_bits(&[1, 5, 6, -2i8 as u8, 7], 5, &mut out);
+        assert_eq!(out, [5, 6, 7, 7, 7]);
+        // too long, then too short
+        unpack_bits(&[-3i8 as u8, 1], 2, &mut out);
+        unpack_bits(&[0, 9], 3, &mut out);
+        assert_eq!(out, [5, 6, 7, 7, 7, 1, 1, 9, 0, 0]);
+    }
+}
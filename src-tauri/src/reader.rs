@@ -0,0 +1,121 @@
+/// Reading the binary files resources and imports come from (Aseprite files, Photoshop
+/// documents), failing instead of panicking on truncated files.
+use anyhow::{anyhow, Result};
+
+/// The byte order numbers are stored in
+#[derive(Clone, Copy)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pub pos: usize,
+    endian: Endian,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], endian: Endian) -> Self {
+        Self {
+            data,
+            pos: 0,
+            endian,
+        }
+    }
+
+    /// A reader of the next `n` bytes, in the same byte order
+    pub fn sub(&mut self, n: usize) -> Result<Reader<'a>> {
+        Ok(Reader::new(self.bytes(n)?, self.endian))
+    }
+
+    /// Everything that hasn't been read yet
+    pub fn rest(&self) -> &'a [u8] {
+        self.data.get(self.pos..).unwrap_or_default()
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or(anyhow!("unexpected end of file"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into()?)
+    }
+
+    pub fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let bytes = self.array()?;
+        Ok(match self.endian {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let bytes = self.array()?;
+        Ok(match self.endian {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let bytes = self.array()?;
+        Ok(match self.endian {
+            Endian::Little => u64::from_le_bytes(bytes),
+            Endian::Big => u64::from_be_bytes(bytes),
+        })
+    }
+
+    /// A section that starts with its 4 byte length
+    pub fn section(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_either_byte_order() {
+        let data = [0x12, 0x34, 0xff, 0xfe, 0, 0, 0, 1];
+        let mut little = Reader::new(&data, Endian::Little);
+        assert_eq!(little.u16().unwrap(), 0x3412);
+        assert_eq!(little.i16().unwrap(), -257);
+        assert_eq!(little.u32().unwrap(), 0x0100_0000);
+        let mut big = Reader::new(&data, Endian::Big);
+        assert_eq!(big.u16().unwrap(), 0x1234);
+        assert_eq!(big.i16().unwrap(), -2);
+        assert_eq!(big.u32().unwrap(), 1);
+    }
+
+    #[test]
+    fn truncated_reads_fail() {
+        let data = [0, 0, 0, 3, 1, 2];
+        let mut r = Reader::new(&data, Endian::Big);
+        assert!(r.section().is_err());
+        let mut r = Reader::new(&data, Endian::Big);
+        r.bytes(5).unwrap();
+        assert!(r.u16().is_err());
+        assert!(r.bytes(usize::MAX).is_err());
+        assert_eq!(r.rest(), [2]);
+    }
+}
@@ -0,0 +1,199 @@
+/// World space (frame coordinates) bounding boxes of objects, worked out from their
+/// descriptions rather than their triangles, for the UI to select and outline things with.
+/// They're conservative: the box always holds everything that's drawn, but can be a little
+/// bigger (curves are bounded by their control points).
+use std::collections::HashMap;
+
+use lyon::algorithms::aabb::fast_bounding_box;
+use lyon::path::Path;
+use winit::dpi::PhysicalSize;
+
+use crate::interface::{
+    Bounds, Compositions, FrameDescription, Object, ParticleKind, Rect, Transformation2D,
+};
+use crate::signals::MediaResources;
+
+use super::morph;
+use super::particles;
+use super::render_data::{
+    bezier_line_width, frame_description_to_objects, image_size, image_texture, layout_text,
+    shape_path, text_offset, video_size,
+};
+use super::shapes;
+
+/// The part of the world that ends up in the frame
+pub const FRAME: Rect = Rect {
+    x: -1.0,
+    y: -1.0,
+    w: 2.0,
+    h: 2.0,
+};
+
+/// Conservative bounds of a path, or nothing if it's empty
+fn path_bounds(path: &Path) -> Option<Rect> {
+    path.iter().next()?;
+    let bounds = fast_bounding_box(path.iter());
+    Some(Rect {
+        x: bounds.min.x,
+        y: bounds.min.y,
+        w: bounds.width(),
+        h: bounds.height(),
+    })
+}
+
+/// Bounds of an object placed in the frame by `transformation`, at `frame` of the video (or of
+/// the composition it's in). Empty objects (no text, no live particles) have none.
+pub fn object_bounds(
+    object: &Object,
+    transformation: &Transformation2D,
+    frame: usize,
+    resources: &MediaResources,
+    resolution: PhysicalSize<u32>,
+) -> Option<Rect> {
+    let res_aspect_ratio = resolution.width as f32 / resolution.height as f32;
+    // bounds of a box in the object's space
+    let place = |local: Rect| Rect::around(local.corners().map(|p| transformation.apply_to(p)));
+    // same as `place`, but for boxes in square units (see `text`)
+    let place_square = |square: Rect| {
+        place(Rect {
+            x: square.x / res_aspect_ratio,
+            w: square.w / res_aspect_ratio,
+            ..square
+        })
+    };
+    let centered = |w: f32, h: f32| Rect {
+        x: -w / 2.0,
+        y: -h / 2.0,
+        w,
+        h,
+    };
+
+    match object {
+        Object::Bezier(bez) => {
+            // the curve stays inside its control points
+            let points = bez
+                .points
+                .iter()
+                .map(|p| transformation.apply_to([p.x, p.y]));
+            let half_width = bezier_line_width(bez, transformation) / 2.0;
+            Rect::around(points).map(|bounds| bounds.inflate(half_width))
+        }
+        Object::Img(img) => match &img.nine_slice {
+            Some(nine_slice) => place(centered(nine_slice.width, nine_slice.height)),
+            None => {
+                let (_, image) =
+                    image_texture(resources, img.id, img.frame.as_ref(), img.layer.as_deref());
+                let (w, h) = image_size(image, resolution);
+                place(centered(w, h))
+            }
+        },
+        Object::ParticleEmitter(emitter) => {
+            let (w, h) = match emitter.particle {
+                ParticleKind::Img(id) => {
+                    image_size(image_texture(resources, id, None, None).1, resolution)
+                }
+                ParticleKind::Circle | ParticleKind::Square => (1.0, 1.0),
+            };
+            // particles spin, so allow for any angle
+            let radius = (w * w + h * h).sqrt() / 2.0;
+            particles::simulate(emitter, frame)
+                .iter()
+                .filter_map(|particle| {
+                    let r = radius * particle.size;
+                    place(Rect {
+                        x: particle.pos[0] - r,
+                        y: particle.pos[1] - r,
+                        w: 2.0 * r,
+                        h: 2.0 * r,
+                    })
+                })
+                .reduce(|a, b| a.union(&b))
+        }
+        Object::Text(text) => {
+            let layout = layout_text(text, resources, res_aspect_ratio)?;
+            let [dx, dy] = text_offset(text, &layout, res_aspect_ratio);
+            let glyphs = path_bounds(&layout.path)?;
+            place_square(Rect {
+                x: glyphs.x + dx,
+                y: glyphs.y + dy,
+                ..glyphs
+            })
+        }
+        Object::Morph(m) => {
+            let bounds = path_bounds(&morph::morph(m))?;
+            let half_width = m
+                .stroke
+                .as_ref()
+                .map_or(0.0, |stroke| stroke.thickness / 2.0);
+            place_square(bounds.inflate(half_width))
+        }
+        Object::SpeechBubble(bubble) => {
+            // text always fits inside the bubble, so only the bubble and its tail matter
+            let layout = layout_text(&bubble.text, resources, res_aspect_ratio)?;
+            let a = layout.width / 2.0 + bubble.padding;
+            let b = layout.height / 2.0 + bubble.padding;
+            let mut bounds = path_bounds(&shapes::bubble_path(&bubble.style, a, b))?;
+            let tail = bubble.tail.as_ref().and_then(|target| {
+                shapes::tail_path([target.x * res_aspect_ratio, target.y].into(), a, b)
+            });
+            if let Some(tail) = tail.as_ref().and_then(path_bounds) {
+                bounds = bounds.union(&tail);
+            }
+            // the outline is drawn centered on the edge
+            place_square(bounds.inflate(bubble.outline_width))
+        }
+        Object::Video(video) => {
+            let (w, h) = video_size(resources.videos.get(&video.id)?, resolution);
+            place(centered(w, h))
+        }
+        Object::Shape(shape) => {
+            let (path, width) = shape_path(shape, transformation, res_aspect_ratio);
+            let bounds = path_bounds(&path)?.inflate(width / 2.0);
+            // already in the frame, only in square units
+            Some(Rect {
+                x: bounds.x / res_aspect_ratio,
+                w: bounds.w / res_aspect_ratio,
+                ..bounds
+            })
+        }
+        // resolved into their objects by `frame_description_to_objects`
+        Object::Precomp(_) => None,
+    }
+}
+
+/// Bounds of every visible object in a frame, and of every node holding any of them (the
+/// union of everything inside it). `index` is the frame's index in the video.
+pub fn frame_bounds(
+    frame: &FrameDescription,
+    index: usize,
+    compositions: &Compositions,
+    resources: &MediaResources,
+    resolution: PhysicalSize<u32>,
+) -> Vec<Bounds> {
+    let mut nodes: HashMap<Vec<usize>, Rect> = HashMap::new();
+    let mut objects = vec![];
+    let placed = frame_description_to_objects(frame, index, compositions);
+    for (object, transformation, path, object_frame) in placed {
+        let bounds = object_bounds(object, &transformation, object_frame, resources, resolution);
+        let Some(bounds) = bounds else {
+            continue;
+        };
+        for depth in 1..path.len() {
+            let node = path[..depth].to_vec();
+            let union = match nodes.get(&node) {
+                Some(node_bounds) => node_bounds.union(&bounds),
+                None => Rect { ..bounds },
+            };
+            nodes.insert(node, union);
+        }
+        objects.push(Bounds { path, bounds });
+    }
+
+    let mut all: Vec<_> = nodes
+        .into_iter()
+        .map(|(path, bounds)| Bounds { path, bounds })
+        .chain(objects)
+        .collect();
+    all.sort_by(|a, b| a.path.cmp(&b.path));
+    all
+}
@@ -0,0 +1,235 @@
+/// Golden image regression tests for frame rendering.
+///
+/// Every `<name>.json` in the fixtures directory is a `FrameDescription`. It gets rendered
+/// headlessly with the `ImageRenderer` and compared against `<name>.png`. If the fixtures
+/// directory has a `resources.json` (a list of `[id, path]` pairs, like the UI sends to
+/// `update_media_resources`) those resources are loaded first, with paths relative to the
+/// fixtures directory.
+///
+/// Run with `booglanim --golden <dir>`, or `booglanim --golden <dir> --bless` to overwrite the
+/// reference images with the current output. The fixtures in `tests/golden` also run with
+/// `cargo test -- --ignored`.
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use winit::dpi::PhysicalSize;
+
+use crate::{interface::FrameDescription, resources::load_media_resources};
+
+use super::renderers::ImageRenderer;
+
+/// Small enough to keep the reference images light, and 4 * width is a multiple of 256 as
+/// `copy_texture_to_buffer` requires.
+const GOLDEN_RESOLUTION: PhysicalSize<u32> = PhysicalSize::new(640, 360);
+
+/// Per pixel color distance (0 to 1, in YIQ space) below which two pixels count as the same.
+/// This absorbs small anti-aliasing and driver differences.
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+/// Fraction of the pixels that are allowed to differ before the fixture fails.
+const MAX_DIFF_RATIO: f32 = 0.001;
+
+pub struct GoldenResult {
+    pub name: String,
+    pub outcome: GoldenOutcome,
+}
+
+pub enum GoldenOutcome {
+    Passed,
+    Blessed,
+    MissingReference,
+    Failed { diff_ratio: f32, diff_path: PathBuf },
+}
+
+/// Render every fixture in `fixtures_dir` and compare (or with `bless`, overwrite) its
+/// reference image.
+pub fn run(fixtures_dir: &Path, bless: bool) -> Result<Vec<GoldenResult>> {
+    let resources_path = fixtures_dir.join("resources.json");
+    let res: Vec<(u32, String)> = if resources_path.exists() {
+        serde_json::from_reader(File::open(&resources_path)?)
+            .with_context(|| format!("can't parse {}", resources_path.display()))?
+    } else {
+        vec![]
+    };
+    let media_resources = load_media_resources(fixtures_dir, res)?;
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    });
+    let image_renderer = pollster::block_on(ImageRenderer::new(
+        &instance,
+        target_format(&instance)?,
+        &media_resources,
+        GOLDEN_RESOLUTION,
+    ));
+
+    let mut fixtures: Vec<_> = fs::read_dir(fixtures_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json") && *path != resources_path)
+        .collect();
+    fixtures.sort();
+
+    let mut results = vec![];
+    for fixture in fixtures {
+        let name = fixture.file_stem().unwrap().to_string_lossy().to_string();
+        let frame: FrameDescription = serde_json::from_reader(File::open(&fixture)?)
+            .with_context(|| format!("can't parse fixture {}", fixture.display()))?;
+        // fixtures are single frames, so they can't have compositions
+        let compositions = HashMap::new();
+        let actual =
+            pollster::block_on(image_renderer.render(&frame, 0, &compositions, &media_resources));
+
+        let reference_path = fixture.with_extension("png");
+        let outcome = if bless {
+            actual.save(&reference_path)?;
+            GoldenOutcome::Blessed
+        } else if !reference_path.exists() {
+            GoldenOutcome::MissingReference
+        } else {
+            let expected = image::open(&reference_path)?.to_rgba8();
+            let (diff, diff_ratio) = compare(&expected, &actual);
+            if diff_ratio <= MAX_DIFF_RATIO {
+                GoldenOutcome::Passed
+            } else {
+                let diff_path = fixture.with_extension("diff.png");
+                diff.save(&diff_path)?;
+                actual.save(fixture.with_extension("actual.png"))?;
+                GoldenOutcome::Failed {
+                    diff_ratio,
+                    diff_path,
+                }
+            }
+        };
+        results.push(GoldenResult { name, outcome });
+    }
+    Ok(results)
+}
+
+/// The format to render in. In the app this comes from the window's surface; headless, take
+/// the first of the formats surfaces offer that the device can render to and read back.
+fn target_format(instance: &wgpu::Instance) -> Result<wgpu::TextureFormat> {
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+        .context("no graphics adapter to render the golden images with")?;
+    let usages = wgpu::TextureUsages::RENDER_ATTACHMENT
+        | wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::TEXTURE_BINDING;
+    [
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ]
+    .into_iter()
+    .find(|format| {
+        adapter
+            .get_texture_format_features(*format)
+            .allowed_usages
+            .contains(usages)
+    })
+    .ok_or_else(|| {
+        anyhow!(
+            "{:?} can't render to an sRGB texture",
+            adapter.get_info().name
+        )
+    })
+}
+
+/// Run the golden tests and print a report, for use from the command line.
+pub fn run_and_report(fixtures_dir: &Path, bless: bool) -> Result<()> {
+    let results = run(fixtures_dir, bless)?;
+    let mut failures = 0;
+    for GoldenResult { name, outcome } in &results {
+        match outcome {
+            GoldenOutcome::Passed => println!("ok       {}", name),
+            GoldenOutcome::Blessed => println!("blessed  {}", name),
+            GoldenOutcome::MissingReference => {
+                failures += 1;
+                println!("MISSING  {} (run with --bless to create it)", name)
+            }
+            GoldenOutcome::Failed {
+                diff_ratio,
+                diff_path,
+            } => {
+                failures += 1;
+                println!(
+                    "FAILED   {} ({:.3}% of pixels differ, see {})",
+                    name,
+                    diff_ratio * 100.0,
+                    diff_path.display()
+                )
+            }
+        }
+    }
+    if failures > 0 {
+        Err(anyhow!(
+            "{} of {} golden image(s) failed",
+            failures,
+            results.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Compare two images, returning a diff image (a faded copy of `expected` with the differing
+/// pixels in red) and the fraction of pixels that differ.
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, f32) {
+    if expected.dimensions() != actual.dimensions() {
+        let (w, h) = actual.dimensions();
+        return (ImageBuffer::from_pixel(w, h, Rgba([255, 0, 0, 255])), 1.0);
+    }
+
+    // squared YIQ distance between white and black, the largest possible
+    const MAX_DELTA: f32 = 35215.0;
+    let threshold = MAX_DELTA * PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+
+    let mut differing = 0;
+    let diff = ImageBuffer::from_fn(expected.width(), expected.height(), |x, y| {
+        let (a, b) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if color_delta(a, b) > threshold {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [luma, _, _] = yiq(a);
+            let faded = (255.0 - 0.1 * (255.0 - luma)) as u8;
+            Rgba([faded, faded, faded, 255])
+        }
+    });
+    let ratio = differing as f32 / (expected.width() * expected.height()) as f32;
+    (diff, ratio)
+}
+
+/// Perceptual color distance, weighted in YIQ space (same approach as pixelmatch). Colors are
+/// blended with white according to their alpha first.
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [y1, i1, q1] = yiq(a);
+    let [y2, i2, q2] = yiq(b);
+    let (dy, di, dq) = (y1 - y2, i1 - i2, q1 - q2);
+    0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq
+}
+
+fn yiq(px: &Rgba<u8>) -> [f32; 3] {
+    let alpha = px[3] as f32 / 255.0;
+    let [r, g, b] = [px[0], px[1], px[2]].map(|c| 255.0 + (c as f32 - 255.0) * alpha);
+    [
+        r * 0.2988953 + g * 0.5866225 + b * 0.1144822,
+        r * 0.595978 - g * 0.2741761 - b * 0.3218019,
+        r * 0.2114702 - g * 0.5226171 + b * 0.3111469,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn golden_images() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        super::run_and_report(&fixtures, false).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+/// Finding out what's at a point of a frame: which object is on top there, where that object
+/// is, and what color the rendered pixel is. Objects are tested against the same triangles
+/// they're drawn with, so anything that's visible can be clicked.
+use image::{DynamicImage, GenericImageView};
+use winit::dpi::PhysicalSize;
+
+use crate::interface::{
+    Compositions, FrameDescription, Img, Inspection, Object, Rect, Transformation2D,
+};
+use crate::signals::MediaResources;
+
+use super::bounds::object_bounds;
+use super::render_data::{
+    frame_description_to_objects, image_size, image_texture, object_parts, Part,
+};
+use super::renderers::ImageRenderer;
+
+fn cross(o: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+fn in_triangle(p: [f32; 2], [a, b, c]: [[f32; 2]; 3]) -> bool {
+    let (d0, d1, d2) = (cross(a, b, p), cross(b, c, p), cross(c, a, p));
+    let negative = d0 < 0.0 || d1 < 0.0 || d2 < 0.0;
+    let positive = d0 > 0.0 || d1 > 0.0 || d2 > 0.0;
+    // either winding, points on an edge count as inside
+    !(negative && positive)
+}
+
+fn part_contains(part: &Part, p: [f32; 2]) -> bool {
+    match part {
+        Part::Quad(_, quad) => {
+            let [a, b, c, d] = quad.map(|vertex| vertex.position);
+            in_triangle(p, [a, b, c]) || in_triangle(p, [a, c, d])
+        }
+        Part::Triangles(vertices, indices) => indices.chunks_exact(3).any(|triangle| {
+            in_triangle(
+                p,
+                [0, 1, 2].map(|i| vertices[triangle[i] as usize].position),
+            )
+        }),
+    }
+}
+
+/// Whether `p` lands on a visible (not fully transparent) pixel of an image. The point is
+/// moved into the image's own space, where it's an axis aligned `w` by `h` box.
+fn image_opaque_at(
+    img: &Img,
+    image: &DynamicImage,
+    transformation: &Transformation2D,
+    resolution: PhysicalSize<u32>,
+    p: [f32; 2],
+) -> bool {
+    let Some(inverse) = transformation.inverse() else {
+        return false;
+    };
+    let (w, h) = image_size(image, resolution);
+    let [x, y] = inverse.apply_to(p);
+    let (s, t) = (x / w + 0.5, y / h + 0.5);
+    if !(0.0..=1.0).contains(&s) || !(0.0..=1.0).contains(&t) {
+        return false;
+    }
+    // same texture coordinates as the quad in `object_parts`
+    let (u, v) = match &img.subrect {
+        Some(subrect) => (
+            subrect.x + (subrect.w - subrect.x) * s,
+            (1.0 - subrect.y) + (subrect.y - subrect.h) * t,
+        ),
+        None => (s, 1.0 - t),
+    };
+    let px = ((u * image.width() as f32) as u32).min(image.width() - 1);
+    let py = ((v * image.height() as f32) as u32).min(image.height() - 1);
+    image.get_pixel(px, py)[3] > 0
+}
+
+/// The topmost object at `p` (in frame coordinates), with its path and bounds
+fn hit_test(
+    frame: &FrameDescription,
+    index: usize,
+    compositions: &Compositions,
+    resources: &MediaResources,
+    resolution: PhysicalSize<u32>,
+    p: [f32; 2],
+) -> Option<(Vec<usize>, Option<Rect>)> {
+    let objects = frame_description_to_objects(frame, index, compositions);
+    // objects are drawn in order, so the last one hit is on top
+    for (object, transformation, path, object_frame) in objects.into_iter().rev() {
+        let hit = match object {
+            // clicking through the transparent parts of images is more useful than hitting
+            // their whole quad
+            Object::Img(
+                img @ Img {
+                    nine_slice: None, ..
+                },
+            ) => image_opaque_at(
+                img,
+                image_texture(resources, img.id, img.frame.as_ref(), img.layer.as_deref()).1,
+                &transformation,
+                resolution,
+                p,
+            ),
+            _ => object_parts(object, &transformation, object_frame, resources, resolution)
+                .iter()
+                .any(|part| part_contains(part, p)),
+        };
+        if hit {
+            let bounds =
+                object_bounds(object, &transformation, object_frame, resources, resolution);
+            return Some((path, bounds));
+        }
+    }
+    None
+}
+
+/// Inspect `frame`, the frame at `index` in its video, at `p`, in frame coordinates. Nothing
+/// is found outside of the frame.
+pub async fn inspect(
+    image_renderer: &ImageRenderer,
+    frame: &FrameDescription,
+    index: usize,
+    compositions: &Compositions,
+    resources: &MediaResources,
+    p @ [x, y]: [f32; 2],
+) -> Inspection {
+    if x.abs() > 1.0 || y.abs() > 1.0 {
+        return Inspection {
+            path: None,
+            bounds: None,
+            color: None,
+        };
+    }
+    let resolution = image_renderer.size();
+    let hit = hit_test(frame, index, compositions, resources, resolution, p);
+
+    let image = image_renderer
+        .render(frame, index, compositions, resources)
+        .await;
+    let px = (((x + 1.0) / 2.0 * image.width() as f32) as u32).min(image.width() - 1);
+    let py = (((1.0 - y) / 2.0 * image.height() as f32) as u32).min(image.height() - 1);
+
+    let (path, bounds) = match hit {
+        Some((path, bounds)) => (Some(path), bounds),
+        None => (None, None),
+    };
+    Inspection {
+        path,
+        bounds,
+        color: Some(image.get_pixel(px, py).0),
+    }
+}
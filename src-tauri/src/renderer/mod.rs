@@ -1,18 +1,31 @@
 use crate::{
-    interface::VideoDescription,
-    signals::{ExportVideo, SetFrame, Signal},
+    interface::{Overlays, VideoDescription},
+    mixer::export_wav,
+    signals::{
+        ExportAudio, ExportVideo, GetBeats, GetBounds, GetLipSync, GetWaveform, Inspect, SetFrame,
+        Signal,
+    },
+    waveform::Waveforms,
 };
 
 use self::{
+    bounds::frame_bounds,
+    inspect::inspect,
+    onion_skin::Ghosts,
+    overlays::Playhead,
     renderers::{Renderers, RenderingError},
+    speaker::Speaker,
+    svg::rasterize_svgs,
     video::export_video,
 };
 
 use image::{ImageBuffer, Rgba};
 use std::{
+    collections::HashMap,
+    path::Path,
     sync::{mpsc::Receiver, Arc, Mutex},
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tauri::Manager;
 use winit::dpi::PhysicalSize;
@@ -22,14 +35,30 @@ use winit::{
     window::WindowBuilder,
 };
 
+mod bounds;
+pub mod golden;
+mod inspect;
+mod morph;
+mod onion_skin;
+mod overlays;
+mod particles;
 mod pipelines;
 mod render_data;
 mod renderers;
 mod shader_structs;
+mod shapes;
+mod speaker;
+mod svg;
+mod text;
 mod texture;
 mod video;
+mod yuv;
 
 const RESOLUTION: (u32, u32) = (1920, 1080);
+/// Sent with `export-failed` when an export can't start, and as the answer to requests that
+/// can't be answered, because an export (or an update of the video or its resources) is
+/// holding the locks
+const EXPORT_BUSY: &str = "busy exporting, try again once it's done";
 
 pub fn run(signal_rx: Receiver<Signal>) {
     let (width, height) = RESOLUTION;
@@ -55,10 +84,15 @@ pub fn run(signal_rx: Receiver<Signal>) {
 
     let renderers = Arc::new(pollster::block_on(Renderers::new(
         window,
-        &media_resources.images,
+        &media_resources,
         PhysicalSize::new(1920, 1080),
     )));
 
+    // the preview works without sound, if there's no way to play it
+    let speaker = Speaker::new()
+        .map_err(|e| eprintln!("no audio preview: {:?}", e))
+        .ok();
+
     let mut last_frame_update = Instant::now();
     let mut latest_image = None;
     let media_resources = Arc::new(Mutex::new(media_resources));
@@ -66,10 +100,21 @@ pub fn run(signal_rx: Receiver<Signal>) {
         frames: Vec::new(),
         sounds: Vec::new(),
         fps: 16,
+        compositions: HashMap::new(),
     }));
     let mut reverse = false;
     let mut playing = true;
     let mut frame = 0;
+    let mut ghosts = Ghosts::default();
+    let mut waveforms = Waveforms::default();
+    let mut overlays = Overlays::default();
+    let mut fps = 16;
+    // updates wait here while an export holds the locks, the latest of each kind wins
+    let mut pending_video_description = None;
+    let mut pending_media_resources = None;
+    // svgs need rasterizing again (and textures uploading) once the image renderer is free
+    let mut rasterize_pending = false;
+    let mut textures_pending = false;
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
@@ -101,7 +146,11 @@ pub fn run(signal_rx: Receiver<Signal>) {
 
                 while let Ok(signal) = signal_rx.try_recv() {
                     match signal {
-                        Signal::ExportVideo(ExportVideo { app_handle, path }) => {
+                        Signal::ExportVideo(ExportVideo {
+                            app_handle,
+                            path,
+                            settings,
+                        }) => {
                             let renderers = renderers.clone();
                             let media_resources = media_resources.clone();
                             let video_description = video_description.clone();
@@ -115,24 +164,67 @@ pub fn run(signal_rx: Receiver<Signal>) {
                                     video_description.try_lock(),
                                     media_resources.try_lock(),
                                 ) {
-                                    pollster::block_on(export_video(
+                                    let res = pollster::block_on(export_video(
                                         &image_renderer,
                                         &video_description.frames,
-                                        &media_resources.images,
+                                        &video_description.compositions,
+                                        &video_description.sounds,
+                                        &media_resources,
                                         video_description.fps,
+                                        &settings,
                                         |frame| {
                                             app_handle.emit_all("encoded-frame", frame).unwrap()
                                         },
                                         path,
                                     ));
+                                    if let Err(e) = res {
+                                        app_handle
+                                            .emit_all("export-failed", format!("{:?}", e))
+                                            .unwrap();
+                                    }
+                                } else {
+                                    app_handle.emit_all("export-failed", EXPORT_BUSY).unwrap();
+                                }
+                            });
+                        }
+                        Signal::ExportAudio(ExportAudio { app_handle, path }) => {
+                            let media_resources = media_resources.clone();
+                            let video_description = video_description.clone();
+                            thread::spawn(move || {
+                                if let (Ok(video_description), Ok(media_resources)) =
+                                    (video_description.try_lock(), media_resources.try_lock())
+                                {
+                                    let res = export_wav(
+                                        &video_description.sounds,
+                                        &media_resources,
+                                        video_description.fps,
+                                        video_description.frames.len(),
+                                        Path::new(&path),
+                                    );
+                                    if let Err(e) = res {
+                                        app_handle
+                                            .emit_all("export-failed", format!("{:?}", e))
+                                            .unwrap();
+                                    } else {
+                                        app_handle.emit_all("exported-audio", path).unwrap();
+                                    }
+                                } else {
+                                    app_handle.emit_all("export-failed", EXPORT_BUSY).unwrap();
                                 }
                             });
                         }
                         Signal::SetPlayback(playback) => {
+                            if playback.playing && !playing {
+                                last_frame_update = Instant::now();
+                            }
                             reverse = playback.reverse;
                             playing = playback.playing;
                             if let Some(playback_frame) = playback.frame {
                                 if let Ok(video_description) = video_description.try_lock() {
+                                    let stepped = matches!(
+                                        playback_frame,
+                                        SetFrame::Forward(_) | SetFrame::Back(_)
+                                    );
                                     frame = match playback_frame {
                                         SetFrame::At(playback_frame) => playback_frame,
                                         SetFrame::Forward(next) => {
@@ -140,34 +232,180 @@ pub fn run(signal_rx: Receiver<Signal>) {
                                         }
                                         SetFrame::Back(prev) => frame.saturating_sub(prev),
                                     };
+                                    if stepped && !playing {
+                                        if let Some(speaker) = &speaker {
+                                            speaker.scrub(frame, video_description.fps);
+                                        }
+                                    }
                                 }
                             }
                         }
                         Signal::UpdateVideoDescription(new_video_description) => {
-                            if let Ok(mut video_description) = video_description.try_lock() {
-                                *video_description = new_video_description;
-                            }
+                            pending_video_description = Some(new_video_description);
                         }
                         Signal::UpdateMediaResources(new_media_resources) => {
-                            if let (Ok(mut image_renderer), Ok(mut media_resources)) = (
+                            pending_media_resources = Some(new_media_resources);
+                        }
+                        Signal::SetOnionSkin(onion_skin) => {
+                            ghosts.set(onion_skin);
+                        }
+                        Signal::SetOverlays(new_overlays) => {
+                            overlays = new_overlays;
+                        }
+                        Signal::Inspect(Inspect {
+                            frame: index,
+                            x,
+                            y,
+                            reply,
+                        }) => {
+                            let inspection = match (
                                 renderers.image_renderer.try_lock(),
+                                renderers.window_renderer.try_lock(),
+                                video_description.try_lock(),
                                 media_resources.try_lock(),
                             ) {
-                                *media_resources = new_media_resources;
-                                image_renderer.refresh_texture_pipeline(&media_resources.images);
-                            }
+                                (
+                                    Ok(image_renderer),
+                                    Ok(window_renderer),
+                                    Ok(video_description),
+                                    Ok(media_resources),
+                                ) => match video_description.frames.get(index) {
+                                    Some(inspected) => {
+                                        let size = image_renderer.size();
+                                        let aspect_ratio = size.width as f32 / size.height as f32;
+                                        let p =
+                                            window_renderer.window_to_frame([x, y], aspect_ratio);
+                                        Ok(pollster::block_on(inspect(
+                                            &image_renderer,
+                                            inspected,
+                                            index,
+                                            &video_description.compositions,
+                                            &media_resources,
+                                            p,
+                                        )))
+                                    }
+                                    None => Err(format!("no frame {}", index)),
+                                },
+                                _ => Err(EXPORT_BUSY.to_string()),
+                            };
+                            // the UI might have given up waiting
+                            let _ = reply.send(inspection);
+                        }
+                        Signal::GetBounds(GetBounds {
+                            frame: index,
+                            reply,
+                        }) => {
+                            let bounds =
+                                match (video_description.try_lock(), media_resources.try_lock()) {
+                                    (Ok(video_description), Ok(media_resources)) => {
+                                        match video_description.frames.get(index) {
+                                            Some(measured) => Ok(frame_bounds(
+                                                measured,
+                                                index,
+                                                &video_description.compositions,
+                                                &media_resources,
+                                                PhysicalSize::new(width, height),
+                                            )),
+                                            None => Err(format!("no frame {}", index)),
+                                        }
+                                    }
+                                    _ => Err(EXPORT_BUSY.to_string()),
+                                };
+                            let _ = reply.send(bounds);
+                        }
+                        Signal::GetWaveform(GetWaveform { id, detail, reply }) => {
+                            let waveform =
+                                match (video_description.try_lock(), media_resources.try_lock()) {
+                                    (Ok(video_description), Ok(media_resources)) => {
+                                        match media_resources.sounds.get(&id) {
+                                            Some(audio) => Ok(waveforms.get(
+                                                id,
+                                                audio,
+                                                video_description.fps,
+                                                detail,
+                                            )),
+                                            None => Err(format!("no sound with id {}", id)),
+                                        }
+                                    }
+                                    _ => Err(EXPORT_BUSY.to_string()),
+                                };
+                            let _ = reply.send(waveform);
+                        }
+                        // analysing a sound takes a while, so it's done by whoever asked
+                        Signal::GetLipSync(GetLipSync { id, reply })
+                        | Signal::GetBeats(GetBeats { id, reply }) => {
+                            let sound =
+                                match (video_description.try_lock(), media_resources.try_lock()) {
+                                    (Ok(video_description), Ok(media_resources)) => {
+                                        match media_resources.sounds.get(&id) {
+                                            Some(audio) => {
+                                                Ok((audio.clone(), video_description.fps))
+                                            }
+                                            None => Err(format!("no sound with id {}", id)),
+                                        }
+                                    }
+                                    _ => Err(EXPORT_BUSY.to_string()),
+                                };
+                            let _ = reply.send(sound);
                         }
                     }
                 }
 
+                if pending_video_description.is_some() || pending_media_resources.is_some() {
+                    if let (Ok(mut video_description), Ok(mut media_resources)) =
+                        (video_description.try_lock(), media_resources.try_lock())
+                    {
+                        if let Some(new_video_description) = pending_video_description.take() {
+                            *video_description = new_video_description;
+                        }
+                        if let Some(new_media_resources) = pending_media_resources.take() {
+                            *media_resources = new_media_resources;
+                            waveforms.invalidate();
+                            textures_pending = true;
+                        }
+                        rasterize_pending = true;
+                        ghosts.invalidate();
+                        if let Some(speaker) = &speaker {
+                            speaker.update(&video_description, &media_resources);
+                        }
+                    }
+                }
+                if rasterize_pending {
+                    if let (
+                        Ok(mut image_renderer),
+                        Ok(video_description),
+                        Ok(mut media_resources),
+                    ) = (
+                        renderers.image_renderer.try_lock(),
+                        video_description.try_lock(),
+                        media_resources.try_lock(),
+                    ) {
+                        let size = image_renderer.size();
+                        if rasterize_svgs(&mut media_resources, &video_description, size)
+                            || textures_pending
+                        {
+                            image_renderer.refresh_texture_pipeline(&media_resources);
+                        }
+                        rasterize_pending = false;
+                        textures_pending = false;
+                    }
+                }
+
                 let res = {
-                    if let (Ok(video_description), Ok(media_resources)) =
+                    // new resources can't be drawn before their textures are uploaded
+                    if rasterize_pending {
+                        Err(RenderingError::RendererLockError)
+                    } else if let (Ok(video_description), Ok(media_resources)) =
                         (video_description.try_lock(), media_resources.try_lock())
                     {
-                        let res = pollster::block_on(
-                            renderers
-                                .render(&video_description.frames[frame], &media_resources.images),
-                        );
+                        fps = video_description.fps;
+                        let res = pollster::block_on(renderers.render(
+                            &video_description,
+                            frame,
+                            &media_resources,
+                            &mut ghosts,
+                            &overlays,
+                        ));
                         if frame >= video_description.frames.len() - 1 && !reverse {
                             frame = video_description.frames.len() - 1;
                             playing = false;
@@ -175,11 +413,17 @@ pub fn run(signal_rx: Receiver<Signal>) {
                         if frame == 0 && reverse {
                             playing = false;
                         }
-                        if playing
-                            && (Instant::now() - last_frame_update).as_secs_f64()
-                                > 1.0 / (video_description.fps as f64)
-                        {
-                            last_frame_update = Instant::now();
+                        let frame_duration =
+                            Duration::from_secs_f64(1.0 / (video_description.fps as f64));
+                        let since_update = Instant::now() - last_frame_update;
+                        if playing && since_update > frame_duration {
+                            // step on a steady beat, so the picture keeps time with the sound,
+                            // unless it's fallen too far behind to catch up
+                            last_frame_update = if since_update > frame_duration * 2 {
+                                Instant::now()
+                            } else {
+                                last_frame_update + frame_duration
+                            };
                             if reverse {
                                 frame -= 1
                             } else {
@@ -191,6 +435,9 @@ pub fn run(signal_rx: Receiver<Signal>) {
                         Err(RenderingError::RendererLockError)
                     }
                 };
+                if let Some(speaker) = &speaker {
+                    speaker.sync(frame, fps, playing, reverse);
+                }
 
                 let res = match res {
                     // rendered fine, no problems
@@ -204,14 +451,13 @@ pub fn run(signal_rx: Receiver<Signal>) {
                     // one or more of the renderers is busy
                     Err(RenderingError::RendererLockError) => {
                         // try to just render the window with the latest image, if it exists. Otherwise, just render a black screen.
-                        match latest_image.as_ref() {
-                            Some(latest_image) => renderers
-                                .window_renderer
-                                .try_lock()
-                                .unwrap()
-                                .render(latest_image),
-                            None => renderers.window_renderer.try_lock().unwrap().render(&black),
-                        }
+                        let playhead = Playhead { frame, fps };
+                        renderers.window_renderer.try_lock().unwrap().render(
+                            latest_image.as_ref().unwrap_or(&black),
+                            &[],
+                            &overlays,
+                            &playhead,
+                        )
                     }
                 };
 
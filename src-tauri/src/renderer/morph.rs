@@ -0,0 +1,195 @@
+/// Morphing between two vector paths. Both paths are resampled to the same number of points,
+/// evenly spaced along their length, and the points are interpolated before tessellation.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use lyon::math::{point, Point};
+use lyon::path::{iterator::PathIterator, Path, PathEvent};
+
+use crate::interface::{Morph, Segment, VectorPath};
+
+const FLATTEN_TOLERANCE: f32 = 0.0005;
+/// Aligning closed paths takes samples² steps, so there's a limit to how many there can be
+const MAX_SAMPLES: usize = 1024;
+/// How many pairs of resampled paths are kept, before starting over
+const MAX_CACHED: usize = 256;
+
+fn pt(p: &crate::interface::Point) -> Point {
+    point(p.x, p.y)
+}
+
+pub fn build_path(path: &VectorPath) -> Path {
+    let mut builder = Path::builder();
+    builder.begin(pt(&path.start));
+    for segment in &path.segments {
+        match segment {
+            Segment::Line(to) => {
+                builder.line_to(pt(to));
+            }
+            Segment::Quadratic { ctrl, to } => {
+                builder.quadratic_bezier_to(pt(ctrl), pt(to));
+            }
+            Segment::Cubic { ctrl1, ctrl2, to } => {
+                builder.cubic_bezier_to(pt(ctrl1), pt(ctrl2), pt(to));
+            }
+        }
+    }
+    builder.end(path.closed);
+    builder.build()
+}
+
+/// `n` points evenly spaced along the path. Closed paths don't repeat their first point.
+fn resample(path: &Path, closed: bool, n: usize) -> Vec<Point> {
+    let mut polyline = vec![];
+    for event in path.iter().flattened(FLATTEN_TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => polyline.push(at),
+            PathEvent::Line { to, .. } => polyline.push(to),
+            PathEvent::End { first, .. } if closed => polyline.push(first),
+            _ => {}
+        }
+    }
+    if polyline.len() < 2 || n < 2 {
+        return vec![polyline.first().copied().unwrap_or(point(0.0, 0.0)); n.max(1)];
+    }
+
+    let lengths: Vec<f32> = polyline
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).length())
+        .collect();
+    let total: f32 = lengths.iter().sum();
+    let intervals = if closed { n } else { n - 1 };
+    let step = total / intervals as f32;
+
+    let mut points = Vec::with_capacity(n);
+    let (mut segment, mut walked) = (0, 0.0);
+    for i in 0..n {
+        let distance = step * i as f32;
+        while segment < lengths.len() - 1 && walked + lengths[segment] < distance {
+            walked += lengths[segment];
+            segment += 1;
+        }
+        let t = if lengths[segment] > 0.0 {
+            ((distance - walked) / lengths[segment]).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        points.push(polyline[segment].lerp(polyline[segment + 1], t));
+    }
+    points
+}
+
+/// For closed paths the resampled points can start anywhere and go either way around, so
+/// pick the rotation and direction of `to` that moves the points the least.
+fn align(from: &[Point], to: Vec<Point>) -> Vec<Point> {
+    let n = to.len();
+    let reversed: Vec<_> = to.iter().rev().copied().collect();
+    let mut best = (f32::INFINITY, to.clone());
+    for candidate in [&to, &reversed] {
+        for offset in 0..n {
+            let cost: f32 = (0..n)
+                .map(|i| (candidate[(i + offset) % n] - from[i]).square_length())
+                .sum();
+            if cost < best.0 {
+                best = (cost, (0..n).map(|i| candidate[(i + offset) % n]).collect());
+            }
+        }
+    }
+    best.1
+}
+
+/// Everything that goes into resampling a pair of paths, bit for bit
+fn cache_key(morph: &Morph, n: usize) -> Vec<u32> {
+    let mut key = vec![n as u32];
+    for path in [&morph.from, &morph.to] {
+        key.extend([path.closed as u32, path.segments.len() as u32]);
+        let mut push = |p: &crate::interface::Point| key.extend([p.x.to_bits(), p.y.to_bits()]);
+        push(&path.start);
+        for segment in &path.segments {
+            match segment {
+                Segment::Line(to) => push(to),
+                Segment::Quadratic { ctrl, to } => [ctrl, to].into_iter().for_each(&mut push),
+                Segment::Cubic { ctrl1, ctrl2, to } => {
+                    [ctrl1, ctrl2, to].into_iter().for_each(&mut push)
+                }
+            }
+        }
+    }
+    key
+}
+
+/// `from` and `to`, resampled to the same number of points
+type Resampled = Arc<(Vec<Point>, Vec<Point>)>;
+
+/// Both paths resampled to `n` points, with `to` aligned to `from`. Only `t` changes from
+/// frame to frame, so these are kept rather than worked out again every time.
+fn resampled(morph: &Morph, closed: bool, n: usize) -> Resampled {
+    static CACHE: OnceLock<Mutex<HashMap<Vec<u32>, Resampled>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let key = cache_key(morph, n);
+    if let Some(found) = cache.lock().unwrap().get(&key) {
+        return found.clone();
+    }
+
+    let from = resample(&build_path(&morph.from), closed, n);
+    let to = resample(&build_path(&morph.to), closed, n);
+    let to = if closed { align(&from, to) } else { to };
+    let found = Arc::new((from, to));
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(key, found.clone());
+    found
+}
+
+/// The path part way (`morph.t`) between `morph.from` and `morph.to`
+pub fn morph(morph: &Morph) -> Path {
+    let closed = morph.from.closed && morph.to.closed;
+    let n = morph.samples.clamp(2, MAX_SAMPLES);
+    let points = resampled(morph, closed, n);
+    let (from, to) = (&points.0, &points.1);
+
+    let t = morph.t;
+    let mut builder = Path::builder();
+    builder.begin(from[0].lerp(to[0], t));
+    for (a, b) in from.iter().zip(to).skip(1) {
+        builder.line_to(a.lerp(*b, t));
+    }
+    builder.end(closed);
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn square(size: f32) -> serde_json::Value {
+        let p = |x: f32, y: f32| json!({ "x": x * size, "y": y * size });
+        json!({
+            "start": p(0.0, 0.0),
+            "segments": [{ "Line": p(1.0, 0.0) }, { "Line": p(1.0, 1.0) }, { "Line": p(0.0, 1.0) }],
+            "closed": true,
+        })
+    }
+
+    #[test]
+    fn samples_are_limited_and_kept() {
+        let m: Morph = serde_json::from_value(json!({
+            "from": square(1.0),
+            "to": square(2.0),
+            "t": 0.5,
+            "samples": 1_000_000,
+        }))
+        .unwrap();
+        let path = morph(&m);
+        // a begin, the points after the first and an end
+        assert_eq!(path.iter().count(), MAX_SAMPLES + 1);
+
+        let first = resampled(&m, true, MAX_SAMPLES);
+        let again = resampled(&m, true, MAX_SAMPLES);
+        assert!(Arc::ptr_eq(&first, &again));
+    }
+}
@@ -0,0 +1,82 @@
+/// Onion skinning for the preview window: the frames around the current one are drawn over it,
+/// tinted and faded, so the spacing of a movement can be judged without flipping between
+/// frames. Only the window renderer ever sees these, so they can't end up in an export.
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+use crate::interface::VideoDescription;
+use crate::signals::{MediaResources, OnionSkin};
+
+use super::renderers::ImageRenderer;
+
+const BEFORE_TINT: [f32; 3] = [1.0, 0.35, 0.35];
+const AFTER_TINT: [f32; 3] = [0.35, 1.0, 0.5];
+
+#[derive(Default)]
+pub struct Ghosts {
+    settings: Option<OnionSkin>,
+    /// rendered foregrounds of nearby frames, by frame index
+    cache: HashMap<usize, RgbaImage>,
+}
+
+impl Ghosts {
+    pub fn set(&mut self, settings: Option<OnionSkin>) {
+        self.settings = settings;
+    }
+
+    /// Forget the rendered frames, because the frames or the resources they use changed
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Frame indices around `frame` with their tints, farthest first so the nearest frames
+    /// end up on top
+    fn around(&self, frame: usize, frame_count: usize) -> Vec<(usize, [f32; 4])> {
+        let Some(settings) = &self.settings else {
+            return vec![];
+        };
+        let tint = |[r, g, b]: [f32; 3], distance: usize, count: usize| {
+            // fade out linearly, so the farthest ghost is still visible
+            let falloff = 1.0 - (distance - 1) as f32 / count as f32;
+            [r, g, b, settings.opacity * falloff]
+        };
+        let mut ghosts = vec![];
+        for distance in (1..=settings.before).rev() {
+            if let Some(i) = frame.checked_sub(distance) {
+                ghosts.push((i, tint(BEFORE_TINT, distance, settings.before)));
+            }
+        }
+        for distance in (1..=settings.after).rev() {
+            if frame + distance < frame_count {
+                ghosts.push((frame + distance, tint(AFTER_TINT, distance, settings.after)));
+            }
+        }
+        ghosts
+    }
+
+    /// The ghosts to draw over `frame`, rendering any that aren't cached yet
+    pub async fn update(
+        &mut self,
+        image_renderer: &ImageRenderer,
+        video: &VideoDescription,
+        frame: usize,
+        resources: &MediaResources,
+    ) -> Vec<(&RgbaImage, [f32; 4])> {
+        let ghosts = self.around(frame, video.frames.len());
+        self.cache
+            .retain(|i, _| ghosts.iter().any(|(ghost, _)| ghost == i));
+        for (i, _) in &ghosts {
+            if !self.cache.contains_key(i) {
+                let image = image_renderer
+                    .render_foreground(&video.frames[*i], *i, &video.compositions, resources)
+                    .await;
+                self.cache.insert(*i, image);
+            }
+        }
+        ghosts
+            .into_iter()
+            .map(|(i, tint)| (&self.cache[&i], tint))
+            .collect()
+    }
+}
@@ -0,0 +1,187 @@
+/// Geometry for the guides drawn over the preview window (safe areas, grids, the frame
+/// counter). Everything is laid out in window pixels so lines stay crisp and one pixel wide
+/// however the frame is letterboxed.
+use winit::dpi::PhysicalSize;
+
+use crate::interface::{Grid, Overlays};
+
+use super::shader_structs::ColorVertex;
+
+const ACTION_SAFE_COLOR: [f32; 4] = [1.0, 0.8, 0.1, 0.8];
+const TITLE_SAFE_COLOR: [f32; 4] = [0.1, 0.8, 1.0, 0.8];
+const GRID_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const HUD_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+/// Keeps a custom grid from running out of u16 indices
+const MAX_GRID_LINES: u32 = 64;
+
+/// 3x5 pixel glyphs, one row per entry with the leftmost pixel in the highest bit
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+const COLON: [u8; 5] = [0b000, 0b010, 0b000, 0b010, 0b000];
+
+/// Where playback is, for the frame counter
+pub struct Playhead {
+    pub frame: usize,
+    pub fps: usize,
+}
+
+impl Playhead {
+    /// hours:minutes:seconds:frames
+    fn timecode(&self) -> String {
+        let fps = self.fps.max(1);
+        let seconds = self.frame / fps;
+        format!(
+            "{:02}:{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.frame % fps
+        )
+    }
+}
+
+/// Pixel space rectangle, y going down from the top of the window
+#[derive(Clone, Copy)]
+struct Rect {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Rect {
+    fn new(x0: f32, y0: f32, x1: f32, y1: f32) -> Rect {
+        Rect { x0, y0, x1, y1 }
+    }
+
+    /// the middle `fraction` of the rectangle
+    fn inset(&self, fraction: f32) -> Rect {
+        let (dx, dy) = (
+            (self.x1 - self.x0) * (1.0 - fraction) / 2.0,
+            (self.y1 - self.y0) * (1.0 - fraction) / 2.0,
+        );
+        Rect::new(self.x0 + dx, self.y0 + dy, self.x1 - dx, self.y1 - dy)
+    }
+}
+
+struct Painter {
+    window: PhysicalSize<u32>,
+    vertices: Vec<ColorVertex>,
+    indices: Vec<u16>,
+}
+
+impl Painter {
+    fn fill(&mut self, rect: Rect, color: [f32; 4]) {
+        let (w, h) = (self.window.width as f32, self.window.height as f32);
+        let ndc = |x: f32, y: f32| [x / w * 2.0 - 1.0, 1.0 - y / h * 2.0];
+        let start = self.vertices.len() as u16;
+        self.vertices.extend(
+            [
+                ndc(rect.x0, rect.y1),
+                ndc(rect.x1, rect.y1),
+                ndc(rect.x1, rect.y0),
+                ndc(rect.x0, rect.y0),
+            ]
+            .map(|position| ColorVertex { position, color }),
+        );
+        self.indices.extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+    }
+
+    fn outline(&mut self, rect: Rect, color: [f32; 4]) {
+        let Rect { x0, y0, x1, y1 } = rect;
+        self.fill(Rect::new(x0, y0, x1, y0 + 1.0), color);
+        self.fill(Rect::new(x0, y1 - 1.0, x1, y1), color);
+        self.fill(Rect::new(x0, y0, x0 + 1.0, y1), color);
+        self.fill(Rect::new(x1 - 1.0, y0, x1, y1), color);
+    }
+
+    fn grid(&mut self, frame: Rect, columns: u32, rows: u32) {
+        for i in 1..columns.min(MAX_GRID_LINES) {
+            let x = frame.x0 + (frame.x1 - frame.x0) * i as f32 / columns as f32;
+            self.fill(Rect::new(x, frame.y0, x + 1.0, frame.y1), GRID_COLOR);
+        }
+        for i in 1..rows.min(MAX_GRID_LINES) {
+            let y = frame.y0 + (frame.y1 - frame.y0) * i as f32 / rows as f32;
+            self.fill(Rect::new(frame.x0, y, frame.x1, y + 1.0), GRID_COLOR);
+        }
+    }
+
+    /// Draw `text` (digits, colons and spaces) on a dark box with its top left at `(x, y)`
+    fn text(&mut self, text: &str, x: f32, y: f32, dot: f32) {
+        let advance = 4.0 * dot;
+        let padding = 2.0 * dot;
+        let width = text.len() as f32 * advance - dot + 2.0 * padding;
+        let height = 5.0 * dot + 2.0 * padding;
+        self.fill(Rect::new(x, y, x + width, y + height), HUD_BACKGROUND);
+        for (i, c) in text.chars().enumerate() {
+            let glyph = match c {
+                ':' => COLON,
+                _ => match c.to_digit(10) {
+                    Some(digit) => DIGITS[digit as usize],
+                    None => continue,
+                },
+            };
+            let left = x + padding + i as f32 * advance;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        let (x0, y0) = (left + column as f32 * dot, y + padding + row as f32 * dot);
+                        self.fill(Rect::new(x0, y0, x0 + dot, y0 + dot), HUD_COLOR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Triangles for the enabled overlays. `frame_size` is the half width and height of the
+/// letterboxed frame in window NDC, as drawn by the window renderer.
+pub fn overlay_geometry(
+    overlays: &Overlays,
+    playhead: &Playhead,
+    window: PhysicalSize<u32>,
+    frame_size: (f32, f32),
+) -> (Vec<ColorVertex>, Vec<u16>) {
+    let (w, h) = (window.width as f32, window.height as f32);
+    let frame = Rect::new(
+        (1.0 - frame_size.0) * w / 2.0,
+        (1.0 - frame_size.1) * h / 2.0,
+        (1.0 + frame_size.0) * w / 2.0,
+        (1.0 + frame_size.1) * h / 2.0,
+    );
+    let mut painter = Painter {
+        window,
+        vertices: vec![],
+        indices: vec![],
+    };
+
+    match &overlays.grid {
+        Some(Grid::Thirds) => painter.grid(frame, 3, 3),
+        Some(Grid::Custom { columns, rows }) => painter.grid(frame, *columns, *rows),
+        None => {}
+    }
+    if let Some(safe_areas) = &overlays.safe_areas {
+        painter.outline(frame.inset(safe_areas.action), ACTION_SAFE_COLOR);
+        painter.outline(frame.inset(safe_areas.title), TITLE_SAFE_COLOR);
+    }
+    if overlays.hud {
+        // big enough to read on high dpi screens, without covering much of a small window
+        let dot = (h / 360.0).round().max(2.0);
+        let text = format!("{}  {}", playhead.frame, playhead.timecode());
+        painter.text(&text, frame.x0 + 2.0 * dot, frame.y0 + 2.0 * dot, dot);
+    }
+
+    (painter.vertices, painter.indices)
+}
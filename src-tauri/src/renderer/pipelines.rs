@@ -1,8 +1,9 @@
 use super::{
+    render_data::TextureKey,
     shader_structs::{ColorVertex, TextureVertex},
     texture::Texture,
 };
-use image::DynamicImage;
+use crate::signals::MediaResources;
 use std::collections::HashMap;
 use wgpu::{
     BindGroup, PipelineLayout, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
@@ -61,8 +62,8 @@ pub fn texture_pipeline(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     format : TextureFormat,
-    images: &HashMap<u32, DynamicImage>,
-) -> (RenderPipeline, HashMap<u32, BindGroup>) {
+    resources: &MediaResources,
+) -> (RenderPipeline, HashMap<TextureKey, BindGroup>) {
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -85,9 +86,22 @@ pub fn texture_pipeline(
         label: Some("texture_bind_group_layout"),
     });
 
-    let textures: HashMap<_, _> = images
+    // every frame of an animation gets its own texture, they're picked when drawing. Frames
+    // of single layers are only uploaded once they're drawn, see `ImageRenderer::render_frame`
+    let images = resources
+        .images
         .iter()
-        .map(|(&id, img)| (id, Texture::from_image(&device, &queue, img, None)))
+        .map(|(&id, img)| (TextureKey::Image(id), img));
+    let animation_frames = resources.animations.iter().flat_map(|(&id, animation)| {
+        animation
+            .frames
+            .iter()
+            .enumerate()
+            .map(move |(i, frame)| (TextureKey::Frame(id, i), frame))
+    });
+    let textures: HashMap<_, _> = images
+        .chain(animation_frames)
+        .map(|(key, img)| (key, Texture::from_image(&device, &queue, img, None)))
         .collect();
 
     let bind_groups: HashMap<_, _> = textures
@@ -160,6 +174,71 @@ pub fn triangle_pipeline(
     device.create_render_pipeline(&pipeline_descriptor)
 }
 
+pub fn gradient_pipeline(
+    device: &wgpu::Device,
+    format: TextureFormat,
+) -> (RenderPipeline, BindGroupLayout) {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some("gradient_bind_group_layout"),
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/gradient.wgsl").into()),
+    });
+
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let targets = [Some(color_target_state(format))];
+    let pipeline_descriptor =
+        render_pipeline_descriptor(&render_pipeline_layout, &shader, &[], &targets);
+    (
+        device.create_render_pipeline(&pipeline_descriptor),
+        bind_group_layout,
+    )
+}
+
+/// Pipelines for the preview overlays, drawn straight to the window: one for plain colored
+/// geometry, and one that fills its triangles with a checkerboard
+pub fn overlay_pipelines(
+    device: &wgpu::Device,
+    format: TextureFormat,
+) -> (RenderPipeline, RenderPipeline) {
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    let vertex_buffers = [ColorVertex::desc()];
+    let targets = [Some(color_target_state(format))];
+    let pipeline = |source: &str| {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline_descriptor =
+            render_pipeline_descriptor(&render_pipeline_layout, &shader, &vertex_buffers, &targets);
+        device.create_render_pipeline(&pipeline_descriptor)
+    };
+    (
+        pipeline(include_str!("../../shaders/overlay.wgsl")),
+        pipeline(include_str!("../../shaders/checkerboard.wgsl")),
+    )
+}
+
 fn color_target_state(format : TextureFormat) -> ColorTargetState {
     ColorTargetState {
         format,
@@ -1,22 +1,29 @@
 use std::collections::{HashMap, VecDeque};
 
 use crate::interface::{
-    Container, FrameDescription, Object, Point, Rect, Transform, Transformation2D,
+    Alignment, AnimationTime, Background, BackgroundImage, Bezier, Compositions, Container, Fit,
+    FrameDescription, Img, Insets, NineSlice, Object, ParticleKind, Point, Rect, Shape, Text,
+    Transform, Transformation2D,
 };
 use crate::renderer::shader_structs::ColorVertex;
+use crate::signals::{Animation, AnimationTag, MediaResources, TagDirection};
+use crate::video_clip::VideoClip;
 
+use anyhow::Result;
 use bytemuck::Pod;
 use image::DynamicImage;
-use lyon::lyon_tessellation::{
-    BuffersBuilder, LineCap, StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers,
-};
 use lyon::path::Path;
 use wgpu::{BindGroup, Device, RenderPipeline};
 
 use wgpu::{util::DeviceExt, Buffer};
 use winit::dpi::PhysicalSize;
 
-use super::shader_structs::TextureVertex;
+use super::bounds::FRAME;
+use super::morph;
+use super::particles;
+use super::shader_structs::{TextureVertex, WHITE};
+use super::shapes::{self, Mesh};
+use super::text::{self, TextLayout};
 
 #[derive(Debug, Clone, Copy)]
 pub struct RenderSettings<'a> {
@@ -28,6 +35,25 @@ pub struct RenderSettings<'a> {
     pub indices_range: (u32, u32),
 }
 
+/// Bind groups of everything quads can be textured with
+pub struct TextureBindGroups<'a> {
+    /// image resources and the frames of animated ones
+    pub images: &'a HashMap<TextureKey, BindGroup>,
+    /// the frames of animation layers drawn so far
+    pub layer_frames: &'a HashMap<TextureKey, BindGroup>,
+    /// the video clip frames drawn recently
+    pub video_frames: &'a HashMap<TextureKey, BindGroup>,
+}
+
+impl<'a> TextureBindGroups<'a> {
+    fn get(&self, texture: &TextureKey) -> Option<&BindGroup> {
+        self.images
+            .get(texture)
+            .or_else(|| self.layer_frames.get(texture))
+            .or_else(|| self.video_frames.get(texture))
+    }
+}
+
 pub struct RenderData<'a> {
     pub buffers: Vec<Buffer>,
     pub render_order: Vec<RenderSettings<'a>>,
@@ -49,192 +75,769 @@ fn slice_to_buffer<T: Pod>(
 
 const RECT: &[u16; 6] = &[0, 1, 2, 0, 2, 3];
 
+/// Byte range of the vertices `start..end` in a buffer of `T`s, for `Buffer::slice`
+fn vertex_byte_range<T>(start: usize, end: usize) -> (u64, u64) {
+    let size = std::mem::size_of::<T>() as u64;
+    (start as u64 * size, end as u64 * size)
+}
+
+/// Quads (in normalized device coordinates) covering the frame with a background image,
+/// according to its fit mode.
+pub fn background_quads(
+    bg: &BackgroundImage,
+    img: &DynamicImage,
+    resolution: PhysicalSize<u32>,
+) -> Vec<[TextureVertex; 4]> {
+    let quad = |x: f32, y: f32, w: f32, h: f32| {
+        [
+            ([x, y], [0.0, 1.0]),
+            ([x + w, y], [1.0, 1.0]),
+            ([x + w, y + h], [1.0, 0.0]),
+            ([x, y + h], [0.0, 0.0]),
+        ]
+        .map(|(position, tex_coords)| TextureVertex {
+            position,
+            tex_coords,
+            tint: WHITE,
+        })
+    };
+    // size of the image in normalized device coordinates, if it's drawn at its own resolution
+    let (w, h) = (
+        2.0 * img.width() as f32 / resolution.width as f32,
+        2.0 * img.height() as f32 / resolution.height as f32,
+    );
+    match bg.fit {
+        Fit::Stretch => vec![quad(-1.0, -1.0, 2.0, 2.0)],
+        Fit::Cover | Fit::Contain => {
+            let (x_scale, y_scale) = (2.0 / w, 2.0 / h);
+            let scale = match bg.fit {
+                Fit::Cover => x_scale.max(y_scale),
+                _ => x_scale.min(y_scale),
+            };
+            let (w, h) = (w * scale, h * scale);
+            vec![quad(-w / 2.0, -h / 2.0, w, h)]
+        }
+        Fit::Tile => {
+            let (w, h) = (w * bg.scale, h * bg.scale);
+            if w <= 0.0 || h <= 0.0 {
+                return vec![];
+            }
+            // tiles start from the top left corner of the frame
+            let (columns, rows) = ((2.0 / w).ceil() as usize, (2.0 / h).ceil() as usize);
+            (0..rows)
+                .flat_map(|row| {
+                    (0..columns).map(move |column| {
+                        quad(-1.0 + column as f32 * w, 1.0 - (row + 1) as f32 * h, w, h)
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// An object with the transformation to apply to it, its path and the frame it's at
+pub type Placed<'a> = (&'a Object, Transformation2D, Vec<usize>, usize);
+
 /// Convert a frame description into a list of Objects to render, along with the transformations to apply
-/// to those objects. Objects are sorted from least to greatest z depth. Nodes that have visible set to false,
-/// and their children, are filtered out.
-fn frame_description_to_objects(frame: &FrameDescription) -> Vec<(&Object, Transformation2D)> {
+/// to those objects and their paths (indices into `frame.things`, then into `children` down to the object).
+/// Objects are sorted from least to greatest z depth. Nodes that have visible set to false, and their
+/// children, are filtered out. Precomps are timed by `index`, the frame's index in the video, and
+/// every object comes with the frame it's at: `index`, or the frame of the composition it's in.
+pub fn frame_description_to_objects<'a>(
+    frame: &'a FrameDescription,
+    index: usize,
+    compositions: &'a Compositions,
+) -> Vec<Placed<'a>> {
+    let identity = Transform::identity().to_transformation();
+    nodes_to_objects(
+        &frame.things,
+        index,
+        compositions,
+        identity,
+        MAX_PRECOMP_DEPTH,
+    )
+}
+
+/// How deep precomps can go inside other precomps, so a composition that (indirectly)
+/// contains itself can't hang the renderer
+const MAX_PRECOMP_DEPTH: usize = 16;
+
+/// Objects in `things`, placed by `transformation`, in the order they're drawn. Precomps are
+/// replaced by the objects of their composition's frame at `frame` (of the video, or of the
+/// composition `things` belong to), all at the precomp's depth; their paths continue from the
+/// precomp's path into the composition's frame.
+fn nodes_to_objects<'a>(
+    things: &'a [crate::interface::Node],
+    frame: usize,
+    compositions: &'a Compositions,
+    transformation: Transformation2D,
+    depth: usize,
+) -> Vec<Placed<'a>> {
     use Container::{Leaf, Node};
     let mut queue = VecDeque::from_iter(
-        frame
-            .things
+        things
             .iter()
-            .map(|node| (node, Transform::identity().to_transformation(), 0.0)),
+            .enumerate()
+            .map(|(i, node)| (node, Transformation2D(transformation.0), 0.0, vec![i])),
     );
     let mut objects = vec![];
-    while let Some((node, global_transform, z)) = queue.pop_front() {
+    while let Some((node, global_transform, z, path)) = queue.pop_front() {
         if !node.visible {
             continue;
         }
         let z = node.z + z;
-        for child in &node.children {
+        for (i, child) in node.children.iter().enumerate() {
             let transformation = global_transform.multiply(&node.transform.to_transformation());
+            let path = [path.as_slice(), &[i]].concat();
             match child {
-                Node(node) => queue.push_front((node, transformation, z)),
-                Leaf(object) => objects.push((object, transformation, z)),
+                Node(node) => queue.push_front((node, transformation, z, path)),
+                Leaf(Object::Precomp(precomp)) => {
+                    let Some(composition) = compositions.get(&precomp.composition) else {
+                        continue;
+                    };
+                    let frames = composition.frames.len();
+                    let Some(local_frame) = precomp.composition_frame(frame, frames) else {
+                        continue;
+                    };
+                    if depth == 0 {
+                        continue;
+                    }
+                    let things = &composition.frames[local_frame];
+                    for (object, transformation, sub_path, object_frame) in nodes_to_objects(
+                        things,
+                        local_frame,
+                        compositions,
+                        transformation,
+                        depth - 1,
+                    ) {
+                        let path = [path.as_slice(), &sub_path].concat();
+                        objects.push((object, transformation, z, path, object_frame));
+                    }
+                }
+                Leaf(object) => objects.push((object, transformation, z, path, frame)),
             }
         }
     }
-    objects.sort_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+    objects.sort_by(|(_, _, a, _, _), (_, _, b, _, _)| a.total_cmp(b));
     objects
         .into_iter()
-        .map(|(object, transformation, _)| (object, transformation))
+        .map(|(object, transformation, _, path, frame)| (object, transformation, path, frame))
         .collect()
 }
 
+/// Vertices and draw calls, collected while walking through the frame
+struct Geometry<'a> {
+    triangle_pipeline: &'a RenderPipeline,
+    texture_pipeline: &'a RenderPipeline,
+    texture_vertices: Vec<TextureVertex>,
+    triangle_vertices: Vec<ColorVertex>,
+    triangle_indices: Vec<u16>,
+    render_order: Vec<RenderSettings<'a>>,
+}
+
+impl<'a> Geometry<'a> {
+    fn push_texture_quad(&mut self, bind_group: &'a BindGroup, quad: [TextureVertex; 4]) {
+        let start = self.texture_vertices.len();
+        self.texture_vertices.extend(quad);
+        self.render_order.push(RenderSettings {
+            pipeline: self.texture_pipeline,
+            bind_group: Some(bind_group),
+            vertices_buffer_id: 0,
+            indices_buffer_id: 1,
+            vertices_range: vertex_byte_range::<TextureVertex>(start, start + 4),
+            indices_range: (0, 6),
+        });
+    }
+
+    /// `indices` index into `vertices`, not into all the triangle vertices
+    fn push_triangles(&mut self, mut vertices: Vec<ColorVertex>, indices: Vec<u16>) {
+        let before = (self.triangle_indices.len(), self.triangle_vertices.len());
+        self.triangle_indices.extend(indices);
+        self.triangle_vertices.append(&mut vertices);
+        let after = (self.triangle_indices.len(), self.triangle_vertices.len());
+
+        self.render_order.push(RenderSettings {
+            pipeline: self.triangle_pipeline,
+            bind_group: None,
+            vertices_buffer_id: 2,
+            indices_buffer_id: 3,
+            indices_range: (before.0 as u32, after.0 as u32),
+            vertices_range: vertex_byte_range::<ColorVertex>(before.1, after.1),
+        });
+    }
+}
+
+/// The 9 quads of a nine slice image, centered on the origin. The corners are drawn at the
+/// size they'd have in an `Img` of scale 1, unless the box is too small to fit them, in which
+/// case they shrink.
+fn nine_slice_quads(
+    nine_slice: &NineSlice,
+    img: &DynamicImage,
+    resolution: PhysicalSize<u32>,
+) -> Vec<[TextureVertex; 4]> {
+    let (img_w, img_h) = (img.width() as f32, img.height() as f32);
+    let (w, h) = image_size(img, resolution);
+    let Insets {
+        left,
+        top,
+        right,
+        bottom,
+    } = nine_slice.insets;
+    let (box_w, box_h) = (nine_slice.width, nine_slice.height);
+
+    // inset sizes in units, scaled down if they don't fit in the box
+    let (left_w, right_w) = (left / img_w * w, right / img_w * w);
+    let (top_h, bottom_h) = (top / img_h * h, bottom / img_h * h);
+    let x_fit = (box_w / (left_w + right_w)).min(1.0);
+    let y_fit = (box_h / (top_h + bottom_h)).min(1.0);
+
+    let xs = [
+        -box_w / 2.0,
+        -box_w / 2.0 + left_w * x_fit,
+        box_w / 2.0 - right_w * x_fit,
+        box_w / 2.0,
+    ];
+    let ys = [
+        -box_h / 2.0,
+        -box_h / 2.0 + bottom_h * y_fit,
+        box_h / 2.0 - top_h * y_fit,
+        box_h / 2.0,
+    ];
+    let us = [0.0, left / img_w, 1.0 - right / img_w, 1.0];
+    // texture coordinates go down from the top of the image
+    let vs = [1.0, 1.0 - bottom / img_h, top / img_h, 0.0];
+
+    let mut quads = vec![];
+    for row in 0..3 {
+        for column in 0..3 {
+            let vertex = |i: usize, j: usize| TextureVertex {
+                position: [xs[column + i], ys[row + j]],
+                tex_coords: [us[column + i], vs[row + j]],
+                tint: WHITE,
+            };
+            quads.push([vertex(0, 0), vertex(1, 0), vertex(1, 1), vertex(0, 1)]);
+        }
+    }
+    quads
+}
+
+/// None if the font isn't loaded (yet), then the text isn't drawn
+pub fn layout_text(
+    text: &Text,
+    resources: &MediaResources,
+    res_aspect_ratio: f32,
+) -> Option<TextLayout> {
+    let font = resources.fonts.get(&text.font)?;
+    match text::layout(text, font, text.width * res_aspect_ratio) {
+        Ok(layout) => Some(layout),
+        Err(e) => {
+            eprintln!("can't lay out text with font {}: {:?}", text.font, e);
+            None
+        }
+    }
+}
+
+/// Where the top left corner of laid out text goes (in square units, see `text`). The text
+/// is aligned inside a box `width` wide, centered on the origin.
+pub fn text_offset(text: &Text, layout: &TextLayout, res_aspect_ratio: f32) -> [f32; 2] {
+    let box_width = text.width * res_aspect_ratio;
+    let x = match text.alignment {
+        Alignment::Left => 0.0,
+        Alignment::Center => (box_width - layout.width) / 2.0,
+        Alignment::Right => box_width - layout.width,
+    } - box_width / 2.0;
+    [x, layout.height / 2.0]
+}
+
+/// Width and height an `Img` of scale 1 is drawn at
+pub fn image_size(img: &DynamicImage, resolution: PhysicalSize<u32>) -> (f32, f32) {
+    size_in_frame(img.width(), img.height(), resolution)
+}
+
+/// Same as `image_size`, for the frames of a video clip
+pub fn video_size(clip: &VideoClip, resolution: PhysicalSize<u32>) -> (f32, f32) {
+    size_in_frame(clip.width, clip.height, resolution)
+}
+
+fn size_in_frame(width: u32, height: u32, resolution: PhysicalSize<u32>) -> (f32, f32) {
+    let img_aspect_ratio = height as f32 / width as f32;
+    let res_aspect_ratio = resolution.width as f32 / resolution.height as f32;
+    (1.0, img_aspect_ratio * res_aspect_ratio)
+}
+
+/// How wide the line of a bezier is drawn, in frame coordinates
+pub fn bezier_line_width(bez: &Bezier, transformation: &Transformation2D) -> f32 {
+    fn dist(a: [f32; 2], b: [f32; 2]) -> f32 {
+        (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)
+    }
+
+    let origin = transformation.apply_to([0.0, 0.0]);
+    let unit_x = transformation.apply_to([1.0, 0.0]);
+    let unit_y = transformation.apply_to([0.0, 1.0]);
+    let x_scaling = dist(unit_x, origin);
+    let y_scaling = dist(unit_y, origin);
+    let scale = x_scaling.min(y_scaling);
+    bez.thickness * scale
+}
+
+/// A shape's outline moved into the frame, but in square units (see `text`) so it's
+/// tessellated as finely across as it is up and down, and how wide its stroke is there.
+pub fn shape_path(
+    shape: &Shape,
+    transformation: &Transformation2D,
+    res_aspect_ratio: f32,
+) -> (Path, f32) {
+    let to_square = |p: [f32; 2]| {
+        let [x, y] = transformation.apply_to(p);
+        [x * res_aspect_ratio, y]
+    };
+    let path = shapes::vector_paths(&shape.paths, to_square);
+    // lines get as much wider as the area of the shape grows
+    let [o, x, y] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]].map(to_square);
+    let area = ((x[0] - o[0]) * (y[1] - o[1]) - (x[1] - o[1]) * (y[0] - o[0])).abs();
+    let width = shape
+        .stroke
+        .as_ref()
+        .map_or(0.0, |stroke| stroke.thickness * area.sqrt());
+    (path, width)
+}
+
+/// What a quad is textured with
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureKey {
+    /// the image resource of the given id
+    Image(u32),
+    /// a frame of the animated image or video clip resource of the given id
+    Frame(u32, usize),
+    /// a frame (the last number) of a layer of the animated resource of the given id
+    Layer(u32, usize, usize),
+}
+
+/// The texture an image resource is drawn with, and its image: the image itself, or a frame
+/// of an animation (its first one if `frame` is `None`), or of one of its layers
+pub fn image_texture<'r>(
+    resources: &'r MediaResources,
+    id: u32,
+    frame: Option<&AnimationTime>,
+    layer: Option<&str>,
+) -> (TextureKey, &'r DynamicImage) {
+    match resources.animations.get(&id) {
+        Some(animation) => {
+            let index = frame.map_or(0, |frame| animation_frame(animation, frame));
+            let layer =
+                layer.and_then(|name| animation.layers.iter().position(|layer| layer.name == name));
+            match layer {
+                Some(layer) => (
+                    TextureKey::Layer(id, layer, index),
+                    &animation.layers[layer].frames[index],
+                ),
+                None => (TextureKey::Frame(id, index), &animation.frames[index]),
+            }
+        }
+        None => (TextureKey::Image(id), &resources.images[&id]),
+    }
+}
+
+/// The frames a tag loops through, in the order they're played
+fn tag_frames(tag: &AnimationTag, frame_count: usize) -> Vec<usize> {
+    let (from, to) = (tag.from.min(frame_count - 1), tag.to.min(frame_count - 1));
+    let forward: Vec<usize> = (from..=to).collect();
+    match tag.direction {
+        TagDirection::Forward => forward,
+        TagDirection::Reverse => forward.into_iter().rev().collect(),
+        // the ends aren't played twice in a row
+        TagDirection::PingPong => {
+            let back = forward.iter().rev().skip(1);
+            let back = back.take(forward.len().saturating_sub(2)).copied();
+            forward.iter().copied().chain(back).collect()
+        }
+    }
+}
+
+fn animation_frame(animation: &Animation, frame: &AnimationTime) -> usize {
+    let (frames, seconds): (Vec<usize>, f32) = match frame {
+        AnimationTime::Frame(index) => return index % animation.frames.len(),
+        AnimationTime::Time(seconds) => ((0..animation.frames.len()).collect(), *seconds),
+        AnimationTime::Tag { tag, time } => match animation.tags.iter().find(|t| &t.name == tag) {
+            Some(tag) => (tag_frames(tag, animation.frames.len()), *time),
+            None => ((0..animation.frames.len()).collect(), *time),
+        },
+    };
+    let (Some(&first), Some(&last)) = (frames.first(), frames.last()) else {
+        return 0;
+    };
+    let total: f32 = frames.iter().map(|&i| animation.durations[i]).sum();
+    // frames without durations are never shown, unless there's nothing else
+    if total <= 0.0 {
+        return first;
+    }
+    let mut t = seconds.rem_euclid(total);
+    for &index in &frames {
+        let duration = animation.durations[index];
+        if t < duration {
+            return index;
+        }
+        t -= duration;
+    }
+    // rounding errors at the very end
+    last
+}
+
+/// A `w` by `h` quad centered on the origin, textured with `subrect` of a texture (all of it
+/// if there's none)
+fn textured_quad(
+    w: f32,
+    h: f32,
+    subrect: Option<&Rect>,
+    transformation: &Transformation2D,
+) -> [TextureVertex; 4] {
+    let subrect = subrect.unwrap_or(&Rect {
+        x: 0.0,
+        y: 0.0,
+        w: 1.0,
+        h: 1.0,
+    });
+    [
+        TextureVertex {
+            position: [-w / 2.0, -h / 2.0],
+            tex_coords: [subrect.x, 1.0 - subrect.y],
+            tint: WHITE,
+        },
+        TextureVertex {
+            position: [w / 2.0, -h / 2.0],
+            tex_coords: [subrect.w, 1.0 - subrect.y],
+            tint: WHITE,
+        },
+        TextureVertex {
+            position: [w / 2.0, h / 2.0],
+            tex_coords: [subrect.w, 1.0 - subrect.h],
+            tint: WHITE,
+        },
+        TextureVertex {
+            position: [-w / 2.0, h / 2.0],
+            tex_coords: [subrect.x, 1.0 - subrect.h],
+            tint: WHITE,
+        },
+    ]
+    .map(|texture_vertex| TextureVertex {
+        position: transformation.apply_to(texture_vertex.position),
+        ..texture_vertex
+    })
+}
+
+/// What an object gets drawn with, in frame coordinates
+pub enum Part {
+    Quad(TextureKey, [TextureVertex; 4]),
+    /// triangles, with indices into their own vertices
+    Triangles(Vec<ColorVertex>, Vec<u16>),
+}
+
+impl Part {
+    /// The box around the part's vertices, if it has any
+    pub fn bounds(&self) -> Option<Rect> {
+        match self {
+            Part::Quad(_, quad) => Rect::around(quad.iter().map(|vertex| vertex.position)),
+            Part::Triangles(vertices, _) => {
+                Rect::around(vertices.iter().map(|vertex| vertex.position))
+            }
+        }
+    }
+}
+
+/// One part per mesh, moved by `place`. Nothing if tessellation failed, so a broken path
+/// leaves out only its object.
+fn triangle_parts(
+    meshes: Result<Vec<Mesh>>,
+    place: impl Fn(Vec<ColorVertex>) -> Vec<ColorVertex>,
+) -> Vec<Part> {
+    match meshes {
+        Ok(meshes) => meshes
+            .into_iter()
+            .map(|(vertices, indices)| Part::Triangles(place(vertices), indices))
+            .collect(),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            vec![]
+        }
+    }
+}
+
+/// Tessellate an object, placed in the frame by `transformation`, at `frame` of the video (or
+/// of the composition it's in)
+pub fn object_parts(
+    object: &Object,
+    transformation: &Transformation2D,
+    frame: usize,
+    resources: &MediaResources,
+    resolution: PhysicalSize<u32>,
+) -> Vec<Part> {
+    let res_aspect_ratio = resolution.width as f32 / resolution.height as f32;
+    let mut parts = vec![];
+    // move vertices from square units (see `text`) into the frame
+    let place = |vertices: Vec<ColorVertex>, [dx, dy]: [f32; 2]| -> Vec<ColorVertex> {
+        vertices
+            .into_iter()
+            .map(|vertex| ColorVertex {
+                position: transformation.apply_to([
+                    (vertex.position[0] + dx) / res_aspect_ratio,
+                    vertex.position[1] + dy,
+                ]),
+                ..vertex
+            })
+            .collect()
+    };
+    match object {
+        Object::Bezier(bez) => {
+            fn pt(pt: &Point) -> lyon::geom::Point<f32> {
+                [pt.x, pt.y].into()
+            }
+
+            fn mid(a: &Point, b: &Point) -> Point {
+                Point {
+                    x: (a.x + b.x) * 0.5,
+                    y: (a.y + b.y) * 0.5,
+                }
+            }
+
+            let points: Vec<_> = bez
+                .points
+                .iter()
+                .map(|p| transformation.apply_to([p.x, p.y]))
+                .map(|p| Point { x: p[0], y: p[1] })
+                .collect();
+
+            let mut path_builder = Path::builder();
+            path_builder.begin(pt(&points[0]));
+            path_builder.cubic_bezier_to(
+                pt(&mid(&points[0], &points[1])),
+                pt(&mid(&points[1], &points[2])),
+                pt(&points[2]),
+            );
+            path_builder.end(false);
+            let path = path_builder.build();
+
+            let width = bezier_line_width(bez, transformation);
+            let meshes = shapes::stroke(&path, width, bez.color.to_linear_rgba(1.0));
+            parts.extend(triangle_parts(meshes, |vertices| vertices));
+        }
+        Object::Img(Img {
+            id,
+            nine_slice: Some(nine_slice),
+            frame,
+            layer,
+            opacity,
+            ..
+        }) => {
+            let (texture, image) = image_texture(resources, *id, frame.as_ref(), layer.as_deref());
+            for quad in nine_slice_quads(nine_slice, image, resolution) {
+                let quad = quad.map(|texture_vertex| TextureVertex {
+                    position: transformation.apply_to(texture_vertex.position),
+                    tint: [1.0, 1.0, 1.0, *opacity],
+                    ..texture_vertex
+                });
+                parts.push(Part::Quad(texture, quad));
+            }
+        }
+        Object::Img(img) => {
+            let (texture, image) =
+                image_texture(resources, img.id, img.frame.as_ref(), img.layer.as_deref());
+            let (w, h) = image_size(image, resolution);
+            let quad = textured_quad(w, h, img.subrect.as_ref(), transformation).map(|vertex| {
+                TextureVertex {
+                    tint: [1.0, 1.0, 1.0, img.opacity],
+                    ..vertex
+                }
+            });
+            parts.push(Part::Quad(texture, quad));
+        }
+        Object::Video(video) => {
+            if let Some(clip) = resources.videos.get(&video.id) {
+                let (w, h) = video_size(clip, resolution);
+                let quad = textured_quad(w, h, None, transformation);
+                let source_frame = video.source_frame.min(clip.frames - 1);
+                let texture = TextureKey::Frame(video.id, source_frame);
+                parts.push(Part::Quad(texture, quad));
+            }
+        }
+        Object::ParticleEmitter(emitter) => {
+            let particles = particles::simulate(emitter, frame);
+            match emitter.particle {
+                ParticleKind::Img(id) => {
+                    let (texture, image) = image_texture(resources, id, None, None);
+                    let (w, h) = image_size(image, resolution);
+                    for particle in particles {
+                        let quad =
+                            particle
+                                .quad(w, h)
+                                .map(|(position, tex_coords)| TextureVertex {
+                                    position: transformation.apply_to(position),
+                                    tex_coords,
+                                    tint: particle.color,
+                                });
+                        parts.push(Part::Quad(texture, quad));
+                    }
+                }
+                ParticleKind::Circle | ParticleKind::Square => {
+                    for chunk in particles.chunks(particles::MAX_PARTICLES_PER_DRAW) {
+                        let (vertices, indices) =
+                            particles::shape_geometry(chunk, &emitter.particle);
+                        let vertices = vertices
+                            .into_iter()
+                            .map(|vertex| ColorVertex {
+                                position: transformation.apply_to(vertex.position),
+                                ..vertex
+                            })
+                            .collect();
+                        parts.push(Part::Triangles(vertices, indices));
+                    }
+                }
+            }
+        }
+        Object::Text(text) => {
+            let Some(layout) = layout_text(text, resources, res_aspect_ratio) else {
+                return parts;
+            };
+            let offset = text_offset(text, &layout, res_aspect_ratio);
+            let meshes = shapes::fill(&layout.path, text.color.to_linear_rgba(1.0));
+            parts.extend(triangle_parts(meshes, |vertices| place(vertices, offset)));
+        }
+        Object::Morph(m) => {
+            let path = morph::morph(m);
+            if let Some(color) = &m.fill {
+                let meshes = shapes::fill(&path, color.to_linear_rgba(1.0));
+                parts.extend(triangle_parts(meshes, |vertices| {
+                    place(vertices, [0.0, 0.0])
+                }));
+            }
+            if let Some(stroke) = &m.stroke {
+                let meshes =
+                    shapes::stroke(&path, stroke.thickness, stroke.color.to_linear_rgba(1.0));
+                parts.extend(triangle_parts(meshes, |vertices| {
+                    place(vertices, [0.0, 0.0])
+                }));
+            }
+        }
+        Object::SpeechBubble(bubble) => {
+            let Some(layout) = layout_text(&bubble.text, resources, res_aspect_ratio) else {
+                return parts;
+            };
+            let a = layout.width / 2.0 + bubble.padding;
+            let b = layout.height / 2.0 + bubble.padding;
+            let shape = shapes::bubble_path(&bubble.style, a, b);
+            let tail = bubble.tail.as_ref().and_then(|target| {
+                shapes::tail_path([target.x * res_aspect_ratio, target.y].into(), a, b)
+            });
+            let fill = bubble.fill.to_linear_rgba(1.0);
+            let outline = bubble.outline.to_linear_rgba(1.0);
+
+            // the outlines are twice as thick as they should be, and the fills cover
+            // the inner half, which also hides the seam between the bubble and the tail
+            let mut pieces = vec![];
+            if bubble.outline_width > 0.0 {
+                pieces.push(shapes::stroke(&shape, bubble.outline_width * 2.0, outline));
+                if let Some(tail) = &tail {
+                    pieces.push(shapes::stroke(tail, bubble.outline_width * 2.0, outline));
+                }
+            }
+            if let Some(tail) = &tail {
+                pieces.push(shapes::fill(tail, fill));
+            }
+            pieces.push(shapes::fill(&shape, fill));
+            for meshes in pieces {
+                parts.extend(triangle_parts(meshes, |vertices| {
+                    place(vertices, [0.0, 0.0])
+                }));
+            }
+
+            let meshes = shapes::fill(&layout.path, bubble.text.color.to_linear_rgba(1.0));
+            let offset = [-layout.width / 2.0, layout.height / 2.0];
+            parts.extend(triangle_parts(meshes, |vertices| place(vertices, offset)));
+        }
+        Object::Shape(shape) => {
+            let (path, width) = shape_path(shape, transformation, res_aspect_ratio);
+            // already placed, so only the square units need undoing
+            let unsquare = |vertices: Vec<ColorVertex>| -> Vec<ColorVertex> {
+                vertices
+                    .into_iter()
+                    .map(|vertex| ColorVertex {
+                        position: [vertex.position[0] / res_aspect_ratio, vertex.position[1]],
+                        ..vertex
+                    })
+                    .collect()
+            };
+            if let Some(color) = &shape.fill {
+                let color = color.to_linear_rgba(1.0);
+                let meshes = shapes::fill_with_rule(&path, &shape.fill_rule, color);
+                parts.extend(triangle_parts(meshes, unsquare));
+            }
+            if let Some(stroke) = &shape.stroke {
+                let meshes = shapes::stroke(&path, width, stroke.color.to_linear_rgba(1.0));
+                parts.extend(triangle_parts(meshes, unsquare));
+            }
+        }
+        // resolved into their objects by `frame_description_to_objects`
+        Object::Precomp(_) => {}
+    }
+    parts
+}
+
 impl<'a> RenderData<'a> {
     pub fn new(
         device: &Device,
         resolution: PhysicalSize<u32>,
         frame_description: &FrameDescription,
-        images: &HashMap<u32, DynamicImage>,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
         triangle_pipeline: &'a RenderPipeline,
         texture_pipeline: &'a RenderPipeline,
-        texture_pipeline_bind_groups: &'a HashMap<u32, BindGroup>,
+        bind_groups: &'a TextureBindGroups<'a>,
+        with_background: bool,
     ) -> Self {
-        let mut texture_vertices_index = 0;
-        let mut texture_vertices = vec![];
-
-        let mut triangle_vertices = vec![];
-        let mut triangle_indices = vec![];
-
-        let mut render_order = vec![];
-        for (object, transformation) in frame_description_to_objects(frame_description) {
-            match object {
-                Object::Bezier(bez) => {
-                    fn pt(pt: &Point) -> lyon::geom::Point<f32> {
-                        [pt.x, pt.y].into()
-                    }
+        let images = &resources.images;
+        let mut geometry = Geometry {
+            triangle_pipeline,
+            texture_pipeline,
+            texture_vertices: vec![],
+            triangle_vertices: vec![],
+            triangle_indices: vec![],
+            render_order: vec![],
+        };
 
-                    fn mid(a: &Point, b: &Point) -> Point {
-                        Point {
-                            x: (a.x + b.x) * 0.5,
-                            y: (a.y + b.y) * 0.5,
-                        }
-                    }
-
-                    fn dist(a : [f32; 2], b : [f32; 2]) -> f32 {
-                        (b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)
-                    }
+        match &frame_description.settings.bg {
+            Background::Image(bg) if with_background => {
+                let bind_group = &bind_groups.images[&TextureKey::Image(bg.id)];
+                for quad in background_quads(bg, &images[&bg.id], resolution) {
+                    geometry.push_texture_quad(bind_group, quad);
+                }
+            }
+            _ => {}
+        }
 
-                    let origin = transformation.apply_to([0.0, 0.0]);
-                    let unit_x = transformation.apply_to([1.0, 0.0]);
-                    let unit_y = transformation.apply_to([0.0, 1.0]);
-                    let x_scaling = dist(unit_x, origin);
-                    let y_scaling = dist(unit_y, origin);
-                    let scale = x_scaling.min(y_scaling);
-
-                    let points: Vec<_> = bez
-                        .points
-                        .iter()
-                        .map(|p| transformation.apply_to([p.x, p.y]))
-                        .map(|p| Point { x: p[0], y: p[1] })
-                        .collect();
-
-                    let mut path_builder = Path::builder();
-                    path_builder.begin(pt(&points[0]));
-                    path_builder.cubic_bezier_to(
-                        pt(&mid(&points[0], &points[1])),
-                        pt(&mid(&points[1], &points[2])),
-                        pt(&points[2]),
-                    );
-                    path_builder.end(false);
-                    let path = path_builder.build();
-
-                    let mut tesselator = StrokeTessellator::new();
-                    let mut geometry: VertexBuffers<_, u16> = VertexBuffers::new();
-
-                    let stroke_options = StrokeOptions::default()
-                        .with_line_width(bez.thickness * scale)
-                        .with_line_cap(LineCap::Round)
-                        .with_tolerance(0.001);
-
-                    tesselator
-                        .tessellate_path(
-                            &path,
-                            &stroke_options,
-                            &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| {
-                                ColorVertex {
-                                    position: vertex.position().to_array(),
-                                    color: bez.color.to_linear_rgb(),
-                                }
-                            }),
-                        )
-                        .expect("Error during tessellation");
-
-                    let before = (triangle_indices.len(), triangle_vertices.len());
-                    triangle_indices.extend(geometry.indices.into_iter());
-                    triangle_vertices.append(&mut geometry.vertices);
-                    let after = (triangle_indices.len(), triangle_vertices.len());
-
-                    render_order.push(RenderSettings {
-                        pipeline: triangle_pipeline,
-                        bind_group: None,
-                        vertices_buffer_id: 2,
-                        indices_buffer_id: 3,
-                        indices_range: (before.0 as u32, after.0 as u32),
-                        vertices_range: (before.1 as u64, after.1 as u64),
-                    });
+        let objects = frame_description_to_objects(frame_description, index, compositions);
+        for (object, transformation, _, frame) in objects {
+            for part in object_parts(object, &transformation, frame, resources, resolution) {
+                // don't bother uploading what won't be seen
+                if !part.bounds().is_some_and(|bounds| bounds.overlaps(&FRAME)) {
+                    continue;
                 }
-                Object::Img(img) => {
-                    let tex = &images[&img.id];
-                    let (w, h) = (tex.width(), tex.height());
-                    let img_aspect_ratio = h as f32 / w as f32;
-                    let res_aspect_ratio = resolution.width as f32 / resolution.height as f32;
-                    let (w, h) = (1.0, img_aspect_ratio * res_aspect_ratio);
-                    let subrect = if let Some(subrect) = &img.subrect {
-                        subrect
-                    } else {
-                        &Rect {
-                            x: 0.0,
-                            y: 0.0,
-                            w: 1.0,
-                            h: 1.0,
+                match part {
+                    Part::Quad(texture, quad) => {
+                        // video frames that couldn't be decoded are left out
+                        if let Some(bind_group) = bind_groups.get(&texture) {
+                            geometry.push_texture_quad(bind_group, quad)
                         }
-                    };
-                    texture_vertices.extend(
-                        [
-                            TextureVertex {
-                                position: [-w / 2.0, -h / 2.0],
-                                tex_coords: [subrect.x, 1.0 - subrect.y],
-                            },
-                            TextureVertex {
-                                position: [w / 2.0, -h / 2.0],
-                                tex_coords: [subrect.w, 1.0 - subrect.y],
-                            },
-                            TextureVertex {
-                                position: [w / 2.0, h / 2.0],
-                                tex_coords: [subrect.w, 1.0 - subrect.h],
-                            },
-                            TextureVertex {
-                                position: [-w / 2.0, h / 2.0],
-                                tex_coords: [subrect.x, 1.0 - subrect.h],
-                            },
-                        ]
-                        .into_iter()
-                        .map(|texture_vertex| TextureVertex {
-                            position: transformation.apply_to(texture_vertex.position),
-                            tex_coords: texture_vertex.tex_coords,
-                        }),
-                    );
-
-                    let texture_settings = RenderSettings {
-                        pipeline: texture_pipeline,
-                        bind_group: Some(&texture_pipeline_bind_groups[&img.id]),
-                        vertices_buffer_id: 0,
-                        indices_buffer_id: 1,
-                        vertices_range: (texture_vertices_index, texture_vertices_index + 4),
-                        indices_range: (0, 6),
-                    };
-                    texture_vertices_index += 4;
-                    render_order.push(texture_settings);
+                    }
+                    Part::Triangles(vertices, indices) => {
+                        geometry.push_triangles(vertices, indices)
+                    }
                 }
-                Object::Text(_) => panic!("booglanim does not support text yet, bogly boogly jiggly joogly"),
             }
         }
-        let texture_vertices = slice_to_buffer(device, &texture_vertices, VERTEX);
+        let texture_vertices = slice_to_buffer(device, &geometry.texture_vertices, VERTEX);
         let texture_indicies = slice_to_buffer(device, RECT, INDEX);
-        let triangle_vertices = slice_to_buffer(device, &triangle_vertices, VERTEX);
-        let triangle_indices = slice_to_buffer(device, &triangle_indices, INDEX);
+        let triangle_vertices = slice_to_buffer(device, &geometry.triangle_vertices, VERTEX);
+        let triangle_indices = slice_to_buffer(device, &geometry.triangle_indices, INDEX);
 
         Self {
             buffers: vec![
@@ -243,7 +846,94 @@ impl<'a> RenderData<'a> {
                 triangle_vertices,
                 triangle_indices,
             ],
-            render_order,
+            render_order: geometry.render_order,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn node(leaf: serde_json::Value) -> serde_json::Value {
+        json!({
+            "z": 0.0,
+            "transform": {
+                "pos": { "x": 0.0, "y": 0.0 },
+                "scale": { "x": 1.0, "y": 1.0 },
+                "angle": 0.0,
+            },
+            "visible": true,
+            "children": [{ "Leaf": leaf }],
+        })
+    }
+
+    fn precomp(composition: &str, start: f32) -> serde_json::Value {
+        node(json!({ "Precomp": { "composition": composition, "start": start } }))
+    }
+
+    /// a frame of a composition, marked with its index as the thickness of a line
+    fn marker(i: usize) -> serde_json::Value {
+        let point = json!({ "x": 0.0, "y": 0.0 });
+        node(json!({ "Bezier": {
+            "thickness": i as f32,
+            "color": { "r": 0, "g": 0, "b": 0 },
+            "points": [point, point, point],
+        } }))
+    }
+
+    #[test]
+    fn nested_precomps_use_their_composition_frame() {
+        let compositions: Compositions = serde_json::from_value(json!({
+            "outer": { "frames": (0..4).map(|_| [precomp("inner", 0.0)]).collect::<Vec<_>>() },
+            "inner": { "frames": (0..3).map(|i| [marker(i)]).collect::<Vec<_>>() },
+        }))
+        .unwrap();
+        let frame: FrameDescription = serde_json::from_value(json!({
+            "things": [precomp("outer", 1.0)],
+            "settings": {},
+        }))
+        .unwrap();
+
+        // frame 6 of the video is frame 5 of the outer composition, which loops back to its
+        // frame 1, and that is where the inner composition is at frame 1
+        let objects = frame_description_to_objects(&frame, 6, &compositions);
+        let [(Object::Bezier(bezier), _, path, frame)] = &objects[..] else {
+            panic!("expected one marker");
+        };
+        assert_eq!(bezier.thickness, 1.0);
+        assert_eq!(path, &[0, 0, 0, 0, 0, 0]);
+        assert_eq!(*frame, 1);
+    }
+
+    fn animation(durations: Vec<f32>, tags: Vec<AnimationTag>) -> Animation {
+        Animation {
+            frames: vec![DynamicImage::new_rgba8(1, 1); durations.len()],
+            durations,
+            tags,
+            layers: vec![],
+        }
+    }
+
+    #[test]
+    fn animation_frames_survive_broken_timing() {
+        let tag = |from, to| AnimationTag {
+            name: "tag".to_string(),
+            from,
+            to,
+            direction: TagDirection::Forward,
+        };
+        let time = |time| AnimationTime::Tag {
+            tag: "tag".to_string(),
+            time,
+        };
+        let looping = animation(vec![0.1; 4], vec![tag(3, 1)]);
+        assert_eq!(animation_frame(&looping, &time(0.0)), 0);
+        let looping = animation(vec![0.1; 4], vec![tag(1, 2)]);
+        assert_eq!(animation_frame(&looping, &time(0.15)), 2);
+        let instant = animation(vec![0.0; 3], vec![]);
+        assert_eq!(animation_frame(&instant, &AnimationTime::Time(1.0)), 0);
+    }
+}
@@ -1,14 +1,27 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::iter;
 use std::sync::{Mutex, MutexGuard};
 
-use crate::interface::FrameDescription;
-
-use super::pipelines::{screen_pipeline, texture_pipeline, triangle_pipeline};
-use super::render_data::RenderData;
-use super::shader_structs::TextureVertex;
+use crate::interface::{
+    Background, Compositions, FrameDescription, Gradient, Object, Overlays, VideoDescription,
+};
+use crate::signals::MediaResources;
+use crate::video_clip::CACHED_FRAMES;
+
+use super::onion_skin::Ghosts;
+use super::overlays::{overlay_geometry, Playhead};
+use super::pipelines::{
+    gradient_pipeline, overlay_pipelines, screen_pipeline, texture_pipeline, triangle_pipeline,
+};
+use super::render_data::{
+    frame_description_to_objects, image_texture, RenderData, TextureBindGroups, TextureKey,
+};
+use super::shader_structs::{
+    ColorVertex, GradientUniform, TextureVertex, MAX_GRADIENT_STOPS, WHITE,
+};
 use super::texture::Texture;
-use image::{DynamicImage, ImageBuffer, Rgba};
+use image::{ImageBuffer, Rgba};
 use wgpu::{BindGroup, BindGroupLayout, RenderPipeline, TextureFormat, TextureView};
 
 use wgpu::util::DeviceExt;
@@ -26,11 +39,7 @@ pub enum RenderingError {
 }
 
 impl Renderers {
-    pub async fn new(
-        window: Window,
-        images: &HashMap<u32, DynamicImage>,
-        size: PhysicalSize<u32>,
-    ) -> Self {
+    pub async fn new(window: Window, resources: &MediaResources, size: PhysicalSize<u32>) -> Self {
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -40,7 +49,7 @@ impl Renderers {
 
         let window_renderer = WindowRenderer::new(&instance, window).await;
         let image_renderer =
-            ImageRenderer::new(&instance, window_renderer.config.format, images, size).await;
+            ImageRenderer::new(&instance, window_renderer.config.format, resources, size).await;
 
         Self {
             window_renderer: Mutex::new(window_renderer),
@@ -48,11 +57,15 @@ impl Renderers {
         }
     }
 
-    /// renders a json frame to the window, returning the image that was rendered
+    /// renders a json frame to the window (with the onion skin ghosts of the frames around it
+    /// and the overlays), returning the image that was rendered
     pub async fn render(
         &self,
-        frame: &FrameDescription,
-        images: &HashMap<u32, DynamicImage>,
+        video: &VideoDescription,
+        frame: usize,
+        resources: &MediaResources,
+        ghosts: &mut Ghosts,
+        overlays: &Overlays,
     ) -> Result<ImageBuffer<image::Rgba<u8>, Vec<u8>>, RenderingError> {
         fn lock_renderer<'a, T>(
             renderer: &'a Mutex<T>,
@@ -63,9 +76,18 @@ impl Renderers {
         }
         let image_renderer = lock_renderer(&self.image_renderer)?;
         let window_renderer = lock_renderer(&self.window_renderer)?;
-        let img = image_renderer.render(frame, images).await;
+        let img = image_renderer
+            .render(&video.frames[frame], frame, &video.compositions, resources)
+            .await;
+        let ghosts = ghosts
+            .update(&image_renderer, video, frame, resources)
+            .await;
+        let playhead = Playhead {
+            frame,
+            fps: video.fps,
+        };
         window_renderer
-            .render(&img)
+            .render(&img, &ghosts, overlays, &playhead)
             .map_err(|x| RenderingError::SurfaceError(x))?;
         Ok(img)
     }
@@ -81,6 +103,8 @@ pub struct WindowRenderer {
     size: winit::dpi::PhysicalSize<u32>,
     screen_bind_group_layout: BindGroupLayout,
     screen_pipeline: RenderPipeline,
+    overlay_pipeline: RenderPipeline,
+    checkerboard_pipeline: RenderPipeline,
     window: Window,
 }
 
@@ -139,12 +163,15 @@ impl WindowRenderer {
         surface.configure(&device, &config);
 
         let (screen_pipeline, screen_bind_group_layout) = screen_pipeline(&device, surface_format);
+        let (overlay_pipeline, checkerboard_pipeline) = overlay_pipelines(&device, surface_format);
 
         Self {
             surface,
             config,
             screen_pipeline,
             screen_bind_group_layout,
+            overlay_pipeline,
+            checkerboard_pipeline,
             device,
             queue,
             size,
@@ -152,9 +179,14 @@ impl WindowRenderer {
         }
     }
 
+    /// Letterbox `buffer` into the window, then draw each of `ghosts` over it with its tint,
+    /// and the overlays on top of everything
     pub fn render(
         &self,
         buffer: &ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+        ghosts: &[(&ImageBuffer<image::Rgba<u8>, Vec<u8>>, [f32; 4])],
+        overlays: &Overlays,
+        playhead: &Playhead,
     ) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
         let view: TextureView = output
@@ -167,65 +199,43 @@ impl WindowRenderer {
                 label: Some("Render Encoder"),
             });
 
-        let tex = Texture::from_image_buffer(&self.device, &self.queue, buffer, None);
-
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.screen_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&tex.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
-        let window_ratio = self.size.width as f32 / self.size.height as f32;
-        let aspect_ratio = buffer.width() as f32 / buffer.height() as f32;
-
-        let screen_verts = if window_ratio < aspect_ratio {
-            vec![
-                TextureVertex {
-                    position: [-1.0, -window_ratio / aspect_ratio],
-                    tex_coords: [0.0, 1.0],
-                },
-                TextureVertex {
-                    position: [1.0, -window_ratio / aspect_ratio],
-                    tex_coords: [1.0, 1.0],
-                },
-                TextureVertex {
-                    position: [1.0, window_ratio / aspect_ratio],
-                    tex_coords: [1.0, 0.0],
-                },
-                TextureVertex {
-                    position: [-1.0, window_ratio / aspect_ratio],
-                    tex_coords: [0.0, 0.0],
-                },
-            ]
-        } else {
-            vec![
-                TextureVertex {
-                    position: [-aspect_ratio / window_ratio, -1.0],
-                    tex_coords: [0.0, 1.0],
-                },
-                TextureVertex {
-                    position: [aspect_ratio / window_ratio, -1.0],
-                    tex_coords: [1.0, 1.0],
-                },
-                TextureVertex {
-                    position: [aspect_ratio / window_ratio, 1.0],
-                    tex_coords: [1.0, 0.0],
-                },
-                TextureVertex {
-                    position: [-aspect_ratio / window_ratio, 1.0],
-                    tex_coords: [0.0, 0.0],
-                },
-            ]
-        };
+        let (w, h) = self.letterbox(buffer.width() as f32 / buffer.height() as f32);
+
+        let layers: Vec<_> = iter::once((buffer, WHITE))
+            .chain(ghosts.iter().copied())
+            .collect();
+        let mut screen_verts = vec![];
+        let mut bind_groups = vec![];
+        for (image, tint) in layers {
+            let tex = Texture::from_image_buffer(&self.device, &self.queue, image, None);
+            bind_groups.push(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.screen_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&tex.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&tex.sampler),
+                    },
+                ],
+                label: Some("diffuse_bind_group"),
+            }));
+            screen_verts.extend(
+                [
+                    ([-w, -h], [0.0, 1.0]),
+                    ([w, -h], [1.0, 1.0]),
+                    ([w, h], [1.0, 0.0]),
+                    ([-w, h], [0.0, 0.0]),
+                ]
+                .map(|(position, tex_coords)| TextureVertex {
+                    position,
+                    tex_coords,
+                    tint,
+                }),
+            );
+        }
 
         let vertex_buffer = self
             .device
@@ -243,6 +253,45 @@ impl WindowRenderer {
                 usage: wgpu::BufferUsages::INDEX,
             });
 
+        // the checkerboard quad goes first, drawn with the RECT indices
+        let mut overlay_vertices = vec![];
+        if overlays.checkerboard {
+            overlay_vertices.extend([[-w, -h], [w, -h], [w, h], [-w, h]].map(|position| {
+                ColorVertex {
+                    position,
+                    color: WHITE,
+                }
+            }));
+        }
+        let checkerboard_vertices = overlay_vertices.len() as i32;
+        let (vertices, overlay_indices) = overlay_geometry(overlays, playhead, self.size, (w, h));
+        overlay_vertices.extend(vertices);
+        let overlay_buffers = (!overlay_vertices.is_empty()).then(|| {
+            let buffer = |label, contents: &[u8], usage| {
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(label),
+                        contents,
+                        usage,
+                    })
+            };
+            (
+                buffer(
+                    "Overlay Vertex Buffer",
+                    bytemuck::cast_slice(&overlay_vertices),
+                    wgpu::BufferUsages::VERTEX,
+                ),
+                // wgpu doesn't allow empty buffers
+                (!overlay_indices.is_empty()).then(|| {
+                    buffer(
+                        "Overlay Index Buffer",
+                        bytemuck::cast_slice(&overlay_indices),
+                        wgpu::BufferUsages::INDEX,
+                    )
+                }),
+            )
+        });
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -261,17 +310,61 @@ impl WindowRenderer {
                 })],
                 depth_stencil_attachment: None,
             });
+            if let (Some((overlay_vertex_buffer, _)), true) =
+                (&overlay_buffers, overlays.checkerboard)
+            {
+                render_pass.set_pipeline(&self.checkerboard_pipeline);
+                render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.set_vertex_buffer(0, overlay_vertex_buffer.slice(..));
+                render_pass.draw_indexed(0..RECT.len() as u32, 0, 0..1);
+            }
+
             render_pass.set_pipeline(&self.screen_pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
             render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_pass.draw_indexed(0..RECT.len() as u32, 0, 0..1);
+            for (i, bind_group) in bind_groups.iter().enumerate() {
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw_indexed(0..RECT.len() as u32, 4 * i as i32, 0..1);
+            }
+
+            if let Some((overlay_vertex_buffer, Some(overlay_index_buffer))) = &overlay_buffers {
+                render_pass.set_pipeline(&self.overlay_pipeline);
+                render_pass
+                    .set_index_buffer(overlay_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.set_vertex_buffer(0, overlay_vertex_buffer.slice(..));
+                render_pass.draw_indexed(
+                    0..overlay_indices.len() as u32,
+                    checkerboard_vertices,
+                    0..1,
+                );
+            }
         }
         self.queue.submit(iter::once(encoder.finish()));
         output.present();
         Ok(())
     }
 
+    /// Half the width and height (in window NDC) a frame of `aspect_ratio` is drawn at
+    fn letterbox(&self, aspect_ratio: f32) -> (f32, f32) {
+        let window_ratio = self.size.width as f32 / self.size.height as f32;
+        if window_ratio < aspect_ratio {
+            (1.0, window_ratio / aspect_ratio)
+        } else {
+            (aspect_ratio / window_ratio, 1.0)
+        }
+    }
+
+    /// Where a point of the window (in pixels, from the top left) is in a frame of
+    /// `aspect_ratio`, in frame coordinates. Points in the letterbox end up outside -1 to 1.
+    pub fn window_to_frame(&self, [x, y]: [f32; 2], aspect_ratio: f32) -> [f32; 2] {
+        let (w, h) = self.letterbox(aspect_ratio);
+        let ndc = [
+            x / self.size.width as f32 * 2.0 - 1.0,
+            1.0 - y / self.size.height as f32 * 2.0,
+        ];
+        [ndc[0] / w, ndc[1] / h]
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
@@ -295,8 +388,13 @@ pub struct ImageRenderer {
     queue: wgpu::Queue,
     size: winit::dpi::PhysicalSize<u32>,
     texture_pipeline: RenderPipeline,
-    texture_pipeline_bind_groups: HashMap<u32, BindGroup>,
+    texture_pipeline_bind_groups: HashMap<TextureKey, BindGroup>,
+    /// frames of single animation layers, uploaded the first time they're drawn
+    layer_bind_groups: RefCell<HashMap<TextureKey, BindGroup>>,
+    video_frames: RefCell<VideoFrames>,
     triangle_pipeline: RenderPipeline,
+    gradient_pipeline: RenderPipeline,
+    gradient_bind_group_layout: BindGroupLayout,
     texture_view: TextureView,
     texture: wgpu::Texture,
     format: TextureFormat,
@@ -306,7 +404,7 @@ impl ImageRenderer {
     pub async fn new(
         instance: &wgpu::Instance,
         format: TextureFormat,
-        images: &HashMap<u32, DynamicImage>,
+        resources: &MediaResources,
         size: PhysicalSize<u32>,
     ) -> Self {
         let adapter = instance
@@ -343,8 +441,9 @@ impl ImageRenderer {
         let texture_view = texture.create_view(&Default::default());
 
         let (texture_pipeline, texture_pipeline_bind_groups) =
-            texture_pipeline(&device, &queue, format, &images);
+            texture_pipeline(&device, &queue, format, resources);
         let triangle_pipeline = triangle_pipeline(&device, format);
+        let (gradient_pipeline, gradient_bind_group_layout) = gradient_pipeline(&device, format);
         Self {
             size,
             texture,
@@ -353,31 +452,182 @@ impl ImageRenderer {
             queue,
             texture_pipeline,
             triangle_pipeline,
+            gradient_pipeline,
+            gradient_bind_group_layout,
             texture_pipeline_bind_groups,
+            layer_bind_groups: RefCell::default(),
+            video_frames: RefCell::default(),
             format,
         }
     }
 
+    /// Render `frame`, the frame at `index` in its video
     pub async fn render(
         &self,
         frame: &FrameDescription,
-        images: &HashMap<u32, DynamicImage>,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
+    ) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        self.render_frame(frame, index, compositions, resources, true)
+            .await
+    }
+
+    /// Render only the objects of a frame on a transparent background, for drawing over
+    /// another frame (onion skinning)
+    pub async fn render_foreground(
+        &self,
+        frame: &FrameDescription,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
+    ) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+        self.render_frame(frame, index, compositions, resources, false)
+            .await
+    }
+
+    fn bind_group(&self, texture: &Texture, label: &str) -> BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some(label),
+        })
+    }
+
+    /// Decode and upload the video clip frames shown in `frame` that aren't uploaded yet.
+    /// Unlike images these change from frame to frame, so they can't be uploaded once with
+    /// the resources.
+    fn upload_video_frames(
+        &self,
+        uploaded: &mut VideoFrames,
+        frame: &FrameDescription,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
+    ) {
+        for (object, _, _, _) in frame_description_to_objects(frame, index, compositions) {
+            let Object::Video(video) = object else {
+                continue;
+            };
+            let Some(clip) = resources.videos.get(&video.id) else {
+                continue;
+            };
+            // clips show their last frame once they run out
+            let key = TextureKey::Frame(video.id, video.source_frame.min(clip.frames - 1));
+            if uploaded.used(&key) {
+                continue;
+            }
+            let image = match clip.frame(video.source_frame) {
+                Ok(image) => image,
+                Err(e) => {
+                    eprintln!(
+                        "can't decode frame {} of video {}: {:?}",
+                        video.source_frame, video.id, e
+                    );
+                    continue;
+                }
+            };
+            let texture = Texture::from_image_buffer(&self.device, &self.queue, &image, None);
+            uploaded.insert(key, self.bind_group(&texture, "video_frame_bind_group"));
+        }
+    }
+
+    /// Upload the frames of animation layers shown in `frame` that aren't uploaded yet. An
+    /// animation can have many layers, most of which are never drawn on their own, so they
+    /// aren't uploaded with the resources.
+    fn upload_layer_frames(
+        &self,
+        bind_groups: &mut HashMap<TextureKey, BindGroup>,
+        frame: &FrameDescription,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
+    ) {
+        for (object, _, _, _) in frame_description_to_objects(frame, index, compositions) {
+            let Object::Img(img) = object else {
+                continue;
+            };
+            if img.layer.is_none() || !resources.animations.contains_key(&img.id) {
+                continue;
+            }
+            let (key, image) =
+                image_texture(resources, img.id, img.frame.as_ref(), img.layer.as_deref());
+            if !matches!(key, TextureKey::Layer(..)) || bind_groups.contains_key(&key) {
+                continue;
+            }
+            let texture = Texture::from_image(&self.device, &self.queue, image, None);
+            bind_groups.insert(key, self.bind_group(&texture, "layer_frame_bind_group"));
+        }
+    }
+
+    async fn render_frame(
+        &self,
+        frame: &FrameDescription,
+        index: usize,
+        compositions: &Compositions,
+        resources: &MediaResources,
+        with_background: bool,
     ) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        // taken out while drawing, so it isn't borrowed while waiting for the gpu below
+        let mut layer_frames = self.layer_bind_groups.take();
+        self.upload_layer_frames(&mut layer_frames, frame, index, compositions, resources);
+        let mut video_frames = self.video_frames.take();
+        self.upload_video_frames(&mut video_frames, frame, index, compositions, resources);
+        let bind_groups = TextureBindGroups {
+            images: &self.texture_pipeline_bind_groups,
+            layer_frames: &layer_frames,
+            video_frames: &video_frames.bind_groups,
+        };
         let render_data = RenderData::new(
             &self.device,
             self.size,
             &frame,
-            images,
+            index,
+            compositions,
+            resources,
             &self.triangle_pipeline,
             &self.texture_pipeline,
-            &self.texture_pipeline_bind_groups,
+            &bind_groups,
+            with_background,
         );
 
+        let gradient_bind_group = match &frame.settings.bg {
+            Background::Gradient(gradient) if with_background => {
+                let aspect = self.size.width as f32 / self.size.height as f32;
+                let uniform = gradient_uniform(gradient, aspect);
+                let buffer = self
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Gradient Buffer"),
+                        contents: bytemuck::cast_slice(&[uniform]),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                Some(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.gradient_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("gradient_bind_group"),
+                }))
+            }
+            _ => None,
+        };
+
         let output_buffer_size = (std::mem::size_of::<u32>() as u32
             * self.size.width
             * self.size.height) as wgpu::BufferAddress;
@@ -398,13 +648,23 @@ impl ImageRenderer {
                     view: &self.texture_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(frame.settings.bg.to_wgpu_color()),
+                        load: wgpu::LoadOp::Clear(if with_background {
+                            frame.settings.bg.clear_color()
+                        } else {
+                            wgpu::Color::TRANSPARENT
+                        }),
                         store: true,
                     },
                 })],
                 depth_stencil_attachment: None,
             });
 
+            if let Some(gradient_bind_group) = &gradient_bind_group {
+                render_pass.set_pipeline(&self.gradient_pipeline);
+                render_pass.set_bind_group(0, gradient_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+
             for settings in &render_data.render_order {
                 render_pass.set_pipeline(settings.pipeline);
                 if let Some(bind_group) = settings.bind_group {
@@ -452,6 +712,8 @@ impl ImageRenderer {
         );
 
         self.queue.submit(iter::once(encoder.finish()));
+        self.layer_bind_groups.replace(layer_frames);
+        self.video_frames.replace(video_frames);
 
         let buffer = {
             let buffer_slice = output_buffer.slice(..);
@@ -469,6 +731,16 @@ impl ImageRenderer {
 
             let mut buf = vec![];
             data.clone_into(&mut buf);
+            // everything is drawn with red and blue swapped, so that a BGRA target holds RGBA
+            // bytes. Other targets hold BGRA bytes, swap them back.
+            if !matches!(
+                self.format,
+                TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+            ) {
+                for px in buf.chunks_exact_mut(4) {
+                    px.swap(0, 2);
+                }
+            }
 
             let buffer =
                 ImageBuffer::<Rgba<u8>, _>::from_raw(self.size.width, self.size.height, buf)
@@ -480,12 +752,75 @@ impl ImageRenderer {
         buffer
     }
 
-    pub fn refresh_texture_pipeline(&mut self, images: &HashMap<u32, DynamicImage>) {
+    pub fn refresh_texture_pipeline(&mut self, resources: &MediaResources) {
         (self.texture_pipeline, self.texture_pipeline_bind_groups) =
-            texture_pipeline(&self.device, &self.queue, self.format, images);
+            texture_pipeline(&self.device, &self.queue, self.format, resources);
+        self.layer_bind_groups.get_mut().clear();
+        *self.video_frames.get_mut() = VideoFrames::default();
     }
 
     pub fn size(&self) -> PhysicalSize<u32> {
         self.size
     }
 }
+
+/// Uploaded video clip frames. Like the clips' decoded frames, only the `CACHED_FRAMES` most
+/// recently drawn frames of each clip are kept.
+#[derive(Default)]
+struct VideoFrames {
+    bind_groups: HashMap<TextureKey, BindGroup>,
+    /// most recently used last
+    order: VecDeque<TextureKey>,
+}
+
+impl VideoFrames {
+    /// Whether `key` is uploaded, marking it as just used if it is
+    fn used(&mut self, key: &TextureKey) -> bool {
+        let Some(i) = self.order.iter().position(|k| k == key) else {
+            return false;
+        };
+        let key = self.order.remove(i).unwrap();
+        self.order.push_back(key);
+        true
+    }
+
+    fn insert(&mut self, key: TextureKey, bind_group: BindGroup) {
+        let TextureKey::Frame(id, _) = key else {
+            return;
+        };
+        let same_clip = |k: &TextureKey| matches!(k, TextureKey::Frame(other, _) if *other == id);
+        if self.order.iter().filter(|k| same_clip(k)).count() == CACHED_FRAMES {
+            let oldest = self.order.iter().position(same_clip).unwrap();
+            let evicted = self.order.remove(oldest).unwrap();
+            self.bind_groups.remove(&evicted);
+        }
+        self.order.push_back(key);
+        self.bind_groups.insert(key, bind_group);
+    }
+}
+
+fn gradient_uniform(gradient: &Gradient, aspect: f32) -> GradientUniform {
+    let (kind, geometry, stops) = match gradient {
+        Gradient::Linear { from, to, stops } => (0.0, [from.x, from.y, to.x, to.y], stops),
+        Gradient::Radial {
+            center,
+            radius,
+            stops,
+        } => (1.0, [center.x, center.y, *radius, 0.0], stops),
+    };
+    let mut uniform = GradientUniform {
+        stops: [[0.0; 4]; MAX_GRADIENT_STOPS],
+        geometry,
+        info: [
+            kind,
+            stops.len().min(MAX_GRADIENT_STOPS) as f32,
+            aspect,
+            0.0,
+        ],
+    };
+    for (stop, uniform_stop) in stops.iter().zip(uniform.stops.iter_mut()) {
+        let [r, g, b] = stop.color.to_linear_rgb();
+        *uniform_stop = [r, g, b, stop.offset];
+    }
+    uniform
+}
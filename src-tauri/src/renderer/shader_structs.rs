@@ -3,8 +3,12 @@
 pub struct TextureVertex {
     pub position: [f32; 2],
     pub tex_coords: [f32; 2],
+    /// multiplied with the texture color, `WHITE` leaves it as is
+    pub tint: [f32; 4],
 }
 
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
 impl TextureVertex {
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
@@ -22,6 +26,11 @@ impl TextureVertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -31,7 +40,7 @@ impl TextureVertex {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ColorVertex {
     pub position: [f32; 2],
-    pub color: [f32; 3],
+    pub color: [f32; 4],
 }
 
 impl ColorVertex {
@@ -49,9 +58,20 @@ impl ColorVertex {
                 wgpu::VertexAttribute {
                     offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
             ],
         }
     }
 }
+
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+/// Uniforms for `gradient.wgsl`, see there for what each field holds
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GradientUniform {
+    pub stops: [[f32; 4]; MAX_GRADIENT_STOPS],
+    pub geometry: [f32; 4],
+    pub info: [f32; 4],
+}
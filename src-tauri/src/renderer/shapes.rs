@@ -0,0 +1,228 @@
+/// Filling and stroking of vector paths, and the shapes of speech bubbles.
+use std::collections::HashMap;
+use std::f32::consts::{SQRT_2, TAU};
+
+use anyhow::{anyhow, Result};
+use lyon::lyon_tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, LineCap, LineJoin, StrokeOptions,
+    StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+use lyon::math::{point, Box2D, Point};
+use lyon::path::{builder::BorderRadii, Path, Winding};
+
+use crate::interface::{BubbleStyle, FillRule, Segment, VectorPath};
+
+use super::shader_structs::ColorVertex;
+
+const TOLERANCE: f32 = 0.001;
+
+/// Triangles with their own vertices, few enough for their indices to fit in a u16
+pub type Mesh = (Vec<ColorVertex>, Vec<u16>);
+
+/// Triangles covering the inside of `path` (non zero winding, like fonts use)
+pub fn fill(path: &Path, color: [f32; 4]) -> Result<Vec<Mesh>> {
+    fill_with_rule(path, &FillRule::NonZero, color)
+}
+
+/// Triangles covering the inside of `path`, as `rule` decides what's inside
+pub fn fill_with_rule(path: &Path, rule: &FillRule, color: [f32; 4]) -> Result<Vec<Mesh>> {
+    let options = match rule {
+        FillRule::NonZero => FillOptions::non_zero(),
+        FillRule::EvenOdd => FillOptions::even_odd(),
+    };
+    let mut buffers: VertexBuffers<_, u32> = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate_path(
+            path,
+            &options.with_tolerance(TOLERANCE),
+            &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| ColorVertex {
+                position: vertex.position().to_array(),
+                color,
+            }),
+        )
+        .map_err(|e| anyhow!("can't fill path: {:?}", e))?;
+    Ok(split(buffers))
+}
+
+/// Triangles covering a line of `width` along `path`
+pub fn stroke(path: &Path, width: f32, color: [f32; 4]) -> Result<Vec<Mesh>> {
+    let mut buffers: VertexBuffers<_, u32> = VertexBuffers::new();
+    StrokeTessellator::new()
+        .tessellate_path(
+            path,
+            &StrokeOptions::default()
+                .with_line_width(width)
+                .with_line_cap(LineCap::Round)
+                .with_line_join(LineJoin::Round)
+                .with_tolerance(TOLERANCE),
+            &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| ColorVertex {
+                position: vertex.position().to_array(),
+                color,
+            }),
+        )
+        .map_err(|e| anyhow!("can't stroke path: {:?}", e))?;
+    Ok(split(buffers))
+}
+
+/// Break tessellated triangles up into meshes that u16 indices can address. Long captions and
+/// detailed shapes easily need more vertices than that at our tolerance.
+fn split(buffers: VertexBuffers<ColorVertex, u32>) -> Vec<Mesh> {
+    const MAX_VERTICES: usize = u16::MAX as usize + 1;
+    let VertexBuffers { vertices, indices } = buffers;
+    if vertices.len() <= MAX_VERTICES {
+        return vec![(vertices, indices.into_iter().map(|i| i as u16).collect())];
+    }
+
+    let mut meshes = vec![];
+    let mut mesh: Mesh = (vec![], vec![]);
+    // where the vertices of the whole tessellation are in the current mesh
+    let mut moved = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        if mesh.0.len() + 3 > MAX_VERTICES {
+            meshes.push(std::mem::take(&mut mesh));
+            moved.clear();
+        }
+        for &index in triangle {
+            let local = *moved.entry(index).or_insert_with(|| {
+                mesh.0.push(vertices[index as usize]);
+                mesh.0.len() - 1
+            });
+            mesh.1.push(local as u16);
+        }
+    }
+    meshes.push(mesh);
+    meshes
+}
+
+/// One path holding all of `paths`, with every point moved by `map`
+pub fn vector_paths(paths: &[VectorPath], map: impl Fn([f32; 2]) -> [f32; 2]) -> Path {
+    let pt = |p: &crate::interface::Point| Point::from(map([p.x, p.y]));
+    let mut builder = Path::builder();
+    for path in paths {
+        builder.begin(pt(&path.start));
+        for segment in &path.segments {
+            match segment {
+                Segment::Line(to) => {
+                    builder.line_to(pt(to));
+                }
+                Segment::Quadratic { ctrl, to } => {
+                    builder.quadratic_bezier_to(pt(ctrl), pt(to));
+                }
+                Segment::Cubic { ctrl1, ctrl2, to } => {
+                    builder.cubic_bezier_to(pt(ctrl1), pt(ctrl2), pt(to));
+                }
+            }
+        }
+        builder.end(path.closed);
+    }
+    builder.build()
+}
+
+fn ellipse_point(a: f32, b: f32, theta: f32) -> Point {
+    point(a * theta.cos(), b * theta.sin())
+}
+
+/// Outline of a bubble centered on the origin, big enough to hold a `2a` by `2b` box.
+pub fn bubble_path(style: &BubbleStyle, a: f32, b: f32) -> Path {
+    let mut builder = Path::builder();
+    // the smallest ellipse that holds the box
+    let (ea, eb) = (a * SQRT_2, b * SQRT_2);
+    // roughly even bumps and spikes, however wide the bubble is
+    let bumps = |size: f32| {
+        let perimeter = TAU * ((ea * ea + eb * eb) / 2.0).sqrt();
+        ((perimeter / size).round() as usize).clamp(8, 48)
+    };
+    match style {
+        BubbleStyle::Rounded => {
+            builder.add_rounded_rectangle(
+                &Box2D::new(point(-a, -b), point(a, b)),
+                &BorderRadii::new(a.min(b) * 0.5),
+                Winding::Positive,
+            );
+        }
+        BubbleStyle::Cloud => {
+            let n = bumps(a.min(b) * 0.8);
+            builder.begin(ellipse_point(ea, eb, 0.0));
+            for i in 0..n {
+                let (from, to) = (TAU * i as f32 / n as f32, TAU * (i + 1) as f32 / n as f32);
+                let ctrl = ellipse_point(ea * 1.3, eb * 1.3, (from + to) / 2.0);
+                builder.quadratic_bezier_to(ctrl, ellipse_point(ea, eb, to));
+            }
+            builder.end(true);
+        }
+        BubbleStyle::Jagged => {
+            let n = bumps(a.min(b) * 0.5) * 2;
+            builder.begin(ellipse_point(ea, eb, 0.0));
+            for i in 1..n {
+                let spike = if i % 2 == 1 { 1.35 } else { 1.0 };
+                builder.line_to(ellipse_point(
+                    ea * spike,
+                    eb * spike,
+                    TAU * i as f32 / n as f32,
+                ));
+            }
+            builder.end(true);
+        }
+    }
+    builder.build()
+}
+
+/// A triangle from inside a bubble (centered on the origin, holding a `2a` by `2b` box) to
+/// `target`, or nothing if the target is inside the bubble.
+pub fn tail_path(target: Point, a: f32, b: f32) -> Option<Path> {
+    let length = target.to_vector().length();
+    if (target.x / a).powi(2) + (target.y / b).powi(2) <= 1.0 || length == 0.0 {
+        return None;
+    }
+    // the base sits in the middle of the bubble, so the bubble's fill hides it
+    let half_width = a.min(b) * 0.25;
+    let normal = point(-target.y / length, target.x / length);
+    let mut builder = Path::builder();
+    builder.begin(point(normal.x * half_width, normal.y * half_width));
+    builder.line_to(target);
+    builder.line_to(point(-normal.x * half_width, -normal.y * half_width));
+    builder.end(true);
+    Some(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_keeps_every_triangle_addressable() {
+        // a strip of triangles over more vertices than a u16 can index
+        let vertices: Vec<_> = (0..100_000)
+            .map(|i| ColorVertex {
+                position: [i as f32, (i % 2) as f32],
+                color: [1.0; 4],
+            })
+            .collect();
+        let indices: Vec<u32> = (0..vertices.len() as u32 - 2)
+            .flat_map(|i| [i, i + 1, i + 2])
+            .collect();
+        let triangles: Vec<_> = indices
+            .chunks_exact(3)
+            .map(|t| {
+                t.iter()
+                    .map(|&i| vertices[i as usize].position)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let meshes = split(VertexBuffers { vertices, indices });
+        assert!(meshes.len() > 1);
+        let split_triangles: Vec<_> = meshes
+            .iter()
+            .flat_map(|(vertices, indices)| {
+                assert!(vertices.len() <= u16::MAX as usize + 1);
+                indices.chunks_exact(3).map(|t| {
+                    t.iter()
+                        .map(|&i| vertices[i as usize].position)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        assert_eq!(triangles, split_triangles);
+    }
+}
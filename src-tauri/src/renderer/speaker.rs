@@ -0,0 +1,171 @@
+/// Sound for the preview window. The video's sounds are mixed into one track whenever they
+/// change, and the track is played from the frame on screen, forwards or backwards, so it
+/// follows the playback controls. Stepping through frames while paused plays each frame's
+/// slice of sound, to help line things up by ear.
+use anyhow::{anyhow, Result};
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+
+use std::sync::{Arc, Mutex};
+
+use crate::audio::SAMPLE_RATE;
+use crate::interface::VideoDescription;
+use crate::mixer::{frame_to_sample, mixdown};
+use crate::signals::MediaResources;
+
+/// How far, in frames, the sound can drift from the picture before it's moved back
+const MAX_DRIFT: f64 = 2.0;
+
+#[derive(Default)]
+struct Playhead {
+    track: Vec<[f32; 2]>,
+    /// in track samples
+    position: f64,
+    /// track samples played per output sample: 0 when paused, negative in reverse
+    speed: f64,
+    /// where the sound stops when scrubbing
+    stop_at: Option<f64>,
+}
+
+impl Playhead {
+    /// The sample at `position`, moving it on
+    fn next(&mut self) -> [f32; 2] {
+        if self.speed == 0.0 {
+            return [0.0, 0.0];
+        }
+        let index = self.position.floor();
+        let (Some(a), Some(b)) = (
+            self.track.get(index as usize),
+            self.track.get(index as usize + 1),
+        ) else {
+            // ran off either end of the track
+            self.speed = 0.0;
+            return [0.0, 0.0];
+        };
+        let t = (self.position - index) as f32;
+        let sample = [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+
+        self.position += self.speed;
+        if self.stop_at.is_some_and(|stop_at| self.position >= stop_at) {
+            self.speed = 0.0;
+            self.stop_at = None;
+        }
+        if self.position < 0.0 {
+            self.speed = 0.0;
+        }
+        sample
+    }
+}
+
+pub struct Speaker {
+    playhead: Arc<Mutex<Playhead>>,
+    /// track samples per output sample, at normal speed
+    rate: f64,
+    // the sound stops when this is dropped
+    _stream: Stream,
+}
+
+impl Speaker {
+    /// Start playing silence on the default output device
+    pub fn new() -> Result<Speaker> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(anyhow!("no audio output device"))?;
+        let supported = device.default_output_config()?;
+        let config = supported.config();
+        let rate = SAMPLE_RATE as f64 / config.sample_rate.0 as f64;
+        let playhead = Arc::new(Mutex::new(Playhead::default()));
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, playhead.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, playhead.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, playhead.clone())?,
+            format => return Err(anyhow!("unsupported sample format {}", format)),
+        };
+        stream.play()?;
+        Ok(Speaker {
+            playhead,
+            rate,
+            _stream: stream,
+        })
+    }
+
+    /// Mix the video's sounds again, because they or the resources they use changed
+    pub fn update(&self, video_description: &VideoDescription, resources: &MediaResources) {
+        let fps = video_description.fps.max(1);
+        let track = mixdown(
+            &video_description.sounds,
+            resources,
+            fps,
+            video_description.frames.len(),
+        );
+        self.playhead.lock().unwrap().track = track;
+    }
+
+    /// Keep the sound with the picture, which is showing `frame`
+    pub fn sync(&self, frame: usize, fps: usize, playing: bool, reverse: bool) {
+        let fps = fps.max(1);
+        let mut playhead = self.playhead.lock().unwrap();
+        if !playing {
+            // let a scrubbing snippet finish
+            if playhead.stop_at.is_none() {
+                playhead.speed = 0.0;
+            }
+            return;
+        }
+        let start = frame_to_sample(frame, fps) as f64;
+        let frame_length = SAMPLE_RATE as f64 / fps as f64;
+        playhead.stop_at = None;
+        playhead.speed = if reverse { -self.rate } else { self.rate };
+        // playing either way, the sound for a frame is between its start and the next one's
+        if playhead.position < start - MAX_DRIFT * frame_length
+            || playhead.position > start + (1.0 + MAX_DRIFT) * frame_length
+        {
+            playhead.position = if reverse { start + frame_length } else { start };
+        }
+    }
+
+    /// Play the sound of `frame` once, for stepping through frames
+    pub fn scrub(&self, frame: usize, fps: usize) {
+        let fps = fps.max(1);
+        let start = frame_to_sample(frame, fps) as f64;
+        let mut playhead = self.playhead.lock().unwrap();
+        playhead.position = start;
+        playhead.speed = self.rate;
+        playhead.stop_at = Some(frame_to_sample(frame + 1, fps) as f64);
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    playhead: Arc<Mutex<Playhead>>,
+) -> Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut playhead = playhead.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                let [left, right] = playhead.next();
+                match frame {
+                    [mono] => *mono = T::from_sample((left + right) / 2.0),
+                    [l, r, rest @ ..] => {
+                        *l = T::from_sample(left);
+                        *r = T::from_sample(right);
+                        rest.fill(T::EQUILIBRIUM);
+                    }
+                    [] => {}
+                }
+            }
+        },
+        |e| eprintln!("audio preview failed: {}", e),
+        None,
+    )?;
+    Ok(stream)
+}
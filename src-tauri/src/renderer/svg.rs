@@ -0,0 +1,117 @@
+/// Keeping svg resources sharp. They're drawn like any other image, from a rasterized copy in
+/// `MediaResources::images`, which is redrawn at the largest size the video uses it at
+/// whenever the video or the resources change.
+use std::collections::HashMap;
+
+use winit::dpi::PhysicalSize;
+
+use crate::interface::{Background, Img, Object, ParticleEmitter, ParticleKind, VideoDescription};
+use crate::resources::{own_width, rasterize_svg};
+use crate::signals::MediaResources;
+
+use super::particles;
+use super::render_data::{background_quads, frame_description_to_objects, image_size};
+
+/// However far an svg is zoomed into, it's not rasterized wider than this
+const MAX_WIDTH: u32 = 8192;
+/// Widths are rounded up to a multiple of this, so small changes to the video don't each
+/// cause a redraw
+const WIDTH_STEP: u32 = 64;
+
+/// The largest width, in output pixels, each svg resource is drawn at in `video`, as an
+/// `Img`, a particle or a background. Nine slice images get `None`: their insets are in pixels
+/// of the image, so they always use the svg at its own size.
+fn drawn_widths(
+    video: &VideoDescription,
+    resources: &MediaResources,
+    resolution: PhysicalSize<u32>,
+) -> HashMap<u32, Option<f32>> {
+    let mut widths: HashMap<u32, Option<f32>> = HashMap::new();
+    let mut nine_slices = vec![];
+    // make room for an svg drawn on `quad` (its corners in frame coordinates, starting from
+    // the bottom left and going counterclockwise)
+    let mut fit = |id: u32, quad: [[f32; 2]; 4]| {
+        let image = &resources.images[&id];
+        let length =
+            |[x0, y0]: [f32; 2], [x1, y1]: [f32; 2]| ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+        // frame coordinates go from -1 to 1. The image can be stretched more one way than
+        // the other, so the width also has to be enough for the height it's drawn at.
+        let from_width = length(quad[0], quad[1]) / 2.0 * resolution.width as f32;
+        let drawn_height = length(quad[0], quad[3]) / 2.0 * resolution.height as f32;
+        let from_height = drawn_height * image.width() as f32 / image.height() as f32;
+        let width = widths.entry(id).or_insert(Some(0.0));
+        *width = width.map(|width| width.max(from_width).max(from_height));
+    };
+    for (index, frame) in video.frames.iter().enumerate() {
+        if let Background::Image(bg) = &frame.settings.bg {
+            if resources.svgs.contains_key(&bg.id) {
+                let image = &resources.images[&bg.id];
+                // tiles are all the same size, one is enough
+                if let Some(quad) = background_quads(bg, image, resolution).first() {
+                    fit(bg.id, quad.map(|vertex| vertex.position));
+                }
+            }
+        }
+        for (object, transformation, _, object_frame) in
+            frame_description_to_objects(frame, index, &video.compositions)
+        {
+            match object {
+                Object::Img(img @ Img { id, .. }) if resources.svgs.contains_key(id) => {
+                    if img.nine_slice.is_some() {
+                        nine_slices.push(*id);
+                        continue;
+                    }
+                    let (w, h) = image_size(&resources.images[id], resolution);
+                    let corners = [[-w, -h], [w, -h], [w, h], [-w, h]]
+                        .map(|[x, y]| transformation.apply_to([x / 2.0, y / 2.0]));
+                    fit(*id, corners);
+                }
+                Object::ParticleEmitter(
+                    emitter @ ParticleEmitter {
+                        particle: ParticleKind::Img(id),
+                        ..
+                    },
+                ) if resources.svgs.contains_key(id) => {
+                    let (w, h) = image_size(&resources.images[id], resolution);
+                    for particle in particles::simulate(emitter, object_frame) {
+                        let quad = particle.quad(w, h);
+                        fit(*id, quad.map(|(corner, _)| transformation.apply_to(corner)));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for id in nine_slices {
+        widths.insert(id, None);
+    }
+    widths
+}
+
+/// Rasterize every svg resource used in `video` at the size it's drawn at. Returns whether
+/// any image changed, so textures only get uploaded again when needed.
+pub fn rasterize_svgs(
+    resources: &mut MediaResources,
+    video: &VideoDescription,
+    resolution: PhysicalSize<u32>,
+) -> bool {
+    let mut changed = false;
+    for (id, width) in drawn_widths(video, resources, resolution) {
+        let width = match width {
+            Some(width) => ((width.ceil() as u32).div_ceil(WIDTH_STEP) * WIDTH_STEP)
+                .clamp(WIDTH_STEP, MAX_WIDTH),
+            None => own_width(&resources.svgs[&id]),
+        };
+        if resources.images[&id].width() == width {
+            continue;
+        }
+        match rasterize_svg(&resources.svgs[&id], width) {
+            Ok(image) => {
+                resources.images.insert(id, image);
+                changed = true;
+            }
+            Err(e) => eprintln!("can't rasterize svg {}: {:?}", id, e),
+        }
+    }
+    changed
+}
@@ -0,0 +1,141 @@
+/// Text layout. Glyph outlines come straight from the font and are turned into a lyon path,
+/// so text is filled like any other vector shape and stays sharp at any scale.
+///
+/// Layout happens in "square units": the units of the frame's y axis, used for x as well so
+/// glyphs aren't stretched by the aspect ratio of the frame.
+use anyhow::{anyhow, Result};
+use lyon::math::point;
+use lyon::path::{path::Builder, Path};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use crate::interface::{Alignment, Text};
+
+pub struct TextLayout {
+    /// the glyph outlines, with the top left corner of the text at the origin
+    pub path: Path,
+    /// size of the text, as tight as the lines allow
+    pub width: f32,
+    pub height: f32,
+}
+
+struct Line {
+    glyphs: Vec<GlyphId>,
+    width: f32,
+}
+
+struct Outline<'a> {
+    builder: &'a mut Builder,
+    scale: f32,
+    offset: (f32, f32),
+    open: bool,
+}
+
+impl Outline<'_> {
+    fn pt(&self, x: f32, y: f32) -> lyon::math::Point {
+        point(
+            self.offset.0 + x * self.scale,
+            self.offset.1 + y * self.scale,
+        )
+    }
+
+    fn finish(&mut self) {
+        if self.open {
+            self.builder.end(true);
+            self.open = false;
+        }
+    }
+}
+
+impl OutlineBuilder for Outline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish();
+        let to = self.pt(x, y);
+        self.builder.begin(to);
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.pt(x, y);
+        self.builder.line_to(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (ctrl, to) = (self.pt(x1, y1), self.pt(x, y));
+        self.builder.quadratic_bezier_to(ctrl, to);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (ctrl1, ctrl2, to) = (self.pt(x1, y1), self.pt(x2, y2), self.pt(x, y));
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+    }
+
+    fn close(&mut self) {
+        self.finish();
+    }
+}
+
+/// Break the text into lines no wider than `max_width` (in square units) and build the
+/// glyph outlines, aligning the lines with each other according to `text.alignment`.
+pub fn layout(text: &Text, font: &[u8], max_width: f32) -> Result<TextLayout> {
+    let face = Face::parse(font, 0).map_err(|e| anyhow!("can't parse font: {}", e))?;
+    let scale = text.size / face.units_per_em() as f32;
+    let ascender = face.ascender() as f32 * scale;
+    let line_height = ascender - face.descender() as f32 * scale + face.line_gap() as f32 * scale;
+    let glyph = |c: char| face.glyph_index(c).unwrap_or(GlyphId(0));
+    let advance = |id: GlyphId| face.glyph_hor_advance(id).unwrap_or(0) as f32 * scale;
+    let space = advance(glyph(' '));
+
+    let mut lines = vec![];
+    for paragraph in text.content.split('\n') {
+        let mut line = Line {
+            glyphs: vec![],
+            width: 0.0,
+        };
+        for word in paragraph.split(' ') {
+            let glyphs: Vec<_> = word.chars().map(glyph).collect();
+            let width: f32 = glyphs.iter().map(|&id| advance(id)).sum();
+            if !line.glyphs.is_empty() && line.width + space + width > max_width {
+                lines.push(line);
+                line = Line {
+                    glyphs: vec![],
+                    width: 0.0,
+                };
+            }
+            if !line.glyphs.is_empty() {
+                line.glyphs.push(glyph(' '));
+                line.width += space;
+            }
+            line.glyphs.extend(glyphs);
+            line.width += width;
+        }
+        lines.push(line);
+    }
+
+    let width = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+    let mut builder = Path::builder();
+    for (i, line) in lines.iter().enumerate() {
+        let mut x = match text.alignment {
+            Alignment::Left => 0.0,
+            Alignment::Center => (width - line.width) / 2.0,
+            Alignment::Right => width - line.width,
+        };
+        let baseline = -(i as f32 * line_height) - ascender;
+        for &id in &line.glyphs {
+            let mut outline = Outline {
+                builder: &mut builder,
+                scale,
+                offset: (x, baseline),
+                open: false,
+            };
+            face.outline_glyph(id, &mut outline);
+            outline.finish();
+            x += advance(id);
+        }
+    }
+
+    Ok(TextLayout {
+        path: builder.build(),
+        width,
+        height: lines.len() as f32 * line_height,
+    })
+}
@@ -1,121 +1,245 @@
-use std::{collections::HashMap, path::PathBuf};
-
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use ffmpeg::util::color::{Primaries, TransferCharacteristic};
 use ffmpeg_next as ffmpeg;
-use image::DynamicImage;
-use serde_json::Value;
-use video_rs::{
-    ffmpeg::{
-        ffi::{av_image_copy, av_image_fill_arrays, AVPixelFormat},
-        format::Pixel,
-        util::frame::Video as Frame,
-        Error as FfmpegError, Rescale,
-    },
-    Encoder, EncoderSettings, Locator, Time,
-};
 
-use winit::dpi::PhysicalSize;
+use crate::{
+    audio::SAMPLE_RATE,
+    interface::{AudioDescription, Background, Compositions, ExportSettings, FrameDescription},
+    mixer::{frame_to_sample, mixdown},
+    signals::MediaResources,
+};
 
-use crate::interface::FrameDescription;
+use super::{renderers::ImageRenderer, yuv::rgba_to_yuv_frame};
 
-use super::renderers::ImageRenderer;
+const VIDEO_STREAM: usize = 0;
+const AUDIO_STREAM: usize = 1;
+const AAC_BIT_RATE: usize = 192_000;
 
-async fn export(
+pub async fn export_video(
     image_renderer: &ImageRenderer,
-    frames: &Vec<Vec<Value>>,
-    images: &HashMap<u32, DynamicImage>,
+    frames: &Vec<FrameDescription>,
+    compositions: &Compositions,
+    sounds: &[AudioDescription],
+    resources: &MediaResources,
     fps: usize,
+    settings: &ExportSettings,
     mut on_frame_complete: impl FnMut(usize) -> (),
     path: String,
 ) -> Result<()> {
+    if let Some(frame) = frames
+        .iter()
+        .position(|frame| matches!(frame.settings.bg, Background::Transparent))
+    {
+        bail!(
+            "frame {} has a transparent background, which H.264 video can't store",
+            frame
+        );
+    }
+    let size = image_renderer.size();
     let mut output = ffmpeg::format::output(&path)?;
-    let mut options = ffmpeg::Dictionary::new();
-    // Set H264 encoder to the medium preset.
-    options.set("preset", "medium");
-    // Tune for low latency
-    options.set("tune", "zerolatency");
+    let global_header = output
+        .format()
+        .flags()
+        .contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+
     let codec =
         ffmpeg::encoder::find(ffmpeg::codec::Id::H264).context("could not find h264 codec")?;
-    output.add_stream(codec)?;
+    let mut stream = output.add_stream(codec)?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    encoder.set_width(size.width);
+    encoder.set_height(size.height);
+    encoder.set_format(settings.pixel_format());
+    encoder.set_time_base((1, fps as i32));
+    encoder.set_frame_rate(Some((fps as i32, 1)));
+    // tag the stream with the color space the frames were converted with, so players don't guess.
+    // Only the matrix is up to the settings: the pixels are always rendered with sRGB (BT.709)
+    // primaries and transfer.
+    encoder.set_colorspace(settings.matrix.space());
+    encoder.set_color_range(settings.range.range());
+    unsafe {
+        let context = encoder.as_mut_ptr();
+        (*context).color_primaries = Primaries::BT709.into();
+        (*context).color_trc = TransferCharacteristic::BT709.into();
+    }
+    if global_header {
+        encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+    }
 
-    for (frame_index, frame) in frames.iter().enumerate().into_iter() {}
+    let mut options = ffmpeg::Dictionary::new();
+    options.set("preset", "medium");
+    if settings.ten_bit {
+        options.set("profile", "high10");
+    }
+    let mut encoder = encoder.open_with(options)?;
+    stream.set_parameters(&encoder);
+    stream.set_time_base((1, fps as i32));
+    let encoder_time_base: ffmpeg::Rational = (1, fps as i32).into();
+
+    // silent videos don't get an audio stream at all
+    let has_sound = sounds
+        .iter()
+        .any(|sound| resources.sounds.contains_key(&sound.id));
+    let mut audio = if has_sound {
+        let samples = mixdown(sounds, resources, fps, frames.len());
+        Some(AudioTrack::new(&mut output, samples, global_header)?)
+    } else {
+        None
+    };
+
+    output.write_header()?;
+    let stream_time_base = output.stream(0).unwrap().time_base();
+    if let Some(audio) = &mut audio {
+        audio.stream_time_base = output.stream(AUDIO_STREAM).unwrap().time_base();
+    }
 
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let img = image_renderer
+            .render(frame, frame_index, compositions, resources)
+            .await;
+        let mut frame = rgba_to_yuv_frame(&img, settings);
+        frame.set_pts(Some(frame_index as i64));
+        encoder.send_frame(&frame)?;
+        write_packets(
+            &mut encoder,
+            &mut output,
+            VIDEO_STREAM,
+            encoder_time_base,
+            stream_time_base,
+        )?;
+        // keep the sound up with the picture, so the muxer doesn't have to hold on to much
+        if let Some(audio) = &mut audio {
+            audio.encode_until(frame_to_sample(frame_index + 1, fps), &mut output)?;
+        }
+        on_frame_complete(frame_index);
+    }
+    encoder.send_eof()?;
+    write_packets(
+        &mut encoder,
+        &mut output,
+        VIDEO_STREAM,
+        encoder_time_base,
+        stream_time_base,
+    )?;
+    if let Some(audio) = &mut audio {
+        audio.finish(&mut output)?;
+    }
+    output.write_trailer()?;
     Ok(())
 }
 
-pub async fn export_video(
-    image_renderer: &ImageRenderer,
-    frames: &Vec<FrameDescription>,
-    images: &HashMap<u32, DynamicImage>,
-    fps: usize,
-    mut on_frame_complete: impl FnMut(usize) -> (),
-    path: String,
-) {
-    let duration = Time::from_nth_of_a_second(fps);
-    let mut position = Time::zero();
-    let size = image_renderer.size();
-    let (w, h) = (size.width as usize, size.height as usize);
-    let destination: Locator = PathBuf::from(path).into();
-    let settings = EncoderSettings::for_h264_yuv420p(w, h, true);
-    let mut encoder = Encoder::new(&destination, settings).unwrap();
-    for (frame_index, frame) in frames.iter().enumerate().into_iter() {
-        let frame = image_renderer.render(frame, images).await;
-        let mut buf = frame.into_raw();
-        let mut i = 0;
-        buf.retain(|_| {
-            i += 1;
-            i % 4 != 0
-        });
-
-        position = position.aligned_with(&duration).add();
-        let mut frame = unsafe { av_img_frame(buf, size) }.unwrap();
-        let (time, time_base) = position.clone().into_parts();
-        frame.set_pts(time.map(|time| time.rescale(time_base, encoder.time_base())));
-        encoder.encode_raw(frame).unwrap();
-        on_frame_complete(frame_index);
+fn write_packets(
+    encoder: &mut ffmpeg::encoder::Encoder,
+    output: &mut ffmpeg::format::context::Output,
+    stream: usize,
+    encoder_time_base: ffmpeg::Rational,
+    stream_time_base: ffmpeg::Rational,
+) -> Result<()> {
+    let mut packet = ffmpeg::Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.set_stream(stream);
+        packet.rescale_ts(encoder_time_base, stream_time_base);
+        packet.write_interleaved(output)?;
     }
-    encoder.finish().unwrap();
+    Ok(())
 }
 
-unsafe fn av_img_frame(buf: Vec<u8>, size: PhysicalSize<u32>) -> Result<Frame, FfmpegError> {
-    let PhysicalSize { width, height } = size;
-
-    // Temporary frame structure to place correctly formatted data and linesize stuff in, which
-    // we'll copy later.
-    let mut frame_tmp = Frame::empty();
-    let frame_tmp_ptr = frame_tmp.as_mut_ptr();
-
-    // This does not copy the data, but it sets the `frame_tmp` data and linesize pointers
-    // correctly.
-    let bytes_copied = av_image_fill_arrays(
-        (*frame_tmp_ptr).data.as_ptr() as *mut *mut u8,
-        (*frame_tmp_ptr).linesize.as_ptr() as *mut i32,
-        buf.as_ptr(),
-        AVPixelFormat::AV_PIX_FMT_RGB24,
-        width as i32,
-        height as i32,
-        1,
-    );
-
-    if bytes_copied != buf.len() as i32 {
-        return Err(FfmpegError::from(bytes_copied));
+/// The video's mixed down sound, encoded as AAC a frame of samples at a time
+struct AudioTrack {
+    encoder: ffmpeg::encoder::Audio,
+    samples: Vec<[f32; 2]>,
+    /// how many samples have been sent to the encoder
+    sent: usize,
+    stream_time_base: ffmpeg::Rational,
+}
+
+impl AudioTrack {
+    fn new(
+        output: &mut ffmpeg::format::context::Output,
+        samples: Vec<[f32; 2]>,
+        global_header: bool,
+    ) -> Result<AudioTrack> {
+        let codec =
+            ffmpeg::encoder::find(ffmpeg::codec::Id::AAC).context("could not find aac codec")?;
+        let mut stream = output.add_stream(codec)?;
+        let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .audio()?;
+        encoder.set_rate(SAMPLE_RATE as i32);
+        encoder.set_channel_layout(ffmpeg::ChannelLayout::STEREO);
+        encoder.set_channels(2);
+        encoder.set_format(ffmpeg::format::Sample::F32(
+            ffmpeg::format::sample::Type::Planar,
+        ));
+        encoder.set_bit_rate(AAC_BIT_RATE);
+        encoder.set_time_base((1, SAMPLE_RATE as i32));
+        if global_header {
+            encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+        stream.set_parameters(&encoder);
+        stream.set_time_base((1, SAMPLE_RATE as i32));
+        Ok(AudioTrack {
+            encoder,
+            samples,
+            sent: 0,
+            stream_time_base: (1, SAMPLE_RATE as i32).into(),
+        })
     }
 
-    let mut frame = Frame::new(Pixel::RGB24, width, height);
-    let frame_ptr = frame.as_mut_ptr();
-
-    // Do the actual copying.
-    av_image_copy(
-        (*frame_ptr).data.as_ptr() as *mut *mut u8,
-        (*frame_ptr).linesize.as_ptr() as *mut i32,
-        (*frame_tmp_ptr).data.as_ptr() as *mut *const u8,
-        (*frame_tmp_ptr).linesize.as_ptr(),
-        AVPixelFormat::AV_PIX_FMT_RGB24,
-        width as i32,
-        height as i32,
-    );
-
-    Ok(frame)
-}
+    /// Encode whole encoder frames until `until` samples have been sent, or as close as that
+    /// gets without going over
+    fn encode_until(
+        &mut self,
+        until: usize,
+        output: &mut ffmpeg::format::context::Output,
+    ) -> Result<()> {
+        let frame_size = (self.encoder.frame_size() as usize).max(1);
+        let until = until.min(self.samples.len());
+        while self.sent + frame_size <= until {
+            self.send(frame_size, output)?;
+        }
+        Ok(())
+    }
+
+    /// Encode what's left, the last frame can be short
+    fn finish(&mut self, output: &mut ffmpeg::format::context::Output) -> Result<()> {
+        self.encode_until(self.samples.len(), output)?;
+        if self.sent < self.samples.len() {
+            self.send(self.samples.len() - self.sent, output)?;
+        }
+        self.encoder.send_eof()?;
+        self.write_packets(output)
+    }
 
-fn audio() {}
+    fn send(&mut self, count: usize, output: &mut ffmpeg::format::context::Output) -> Result<()> {
+        let mut frame = ffmpeg::frame::Audio::new(
+            ffmpeg::format::Sample::F32(ffmpeg::format::sample::Type::Planar),
+            count,
+            ffmpeg::ChannelLayout::STEREO,
+        );
+        frame.set_rate(SAMPLE_RATE);
+        let samples = &self.samples[self.sent..self.sent + count];
+        for channel in 0..2 {
+            let plane = frame.plane_mut::<f32>(channel);
+            for (out, sample) in plane.iter_mut().zip(samples) {
+                *out = sample[channel];
+            }
+        }
+        frame.set_pts(Some(self.sent as i64));
+        self.encoder.send_frame(&frame)?;
+        self.sent += count;
+        self.write_packets(output)
+    }
+
+    fn write_packets(&mut self, output: &mut ffmpeg::format::context::Output) -> Result<()> {
+        write_packets(
+            &mut self.encoder,
+            output,
+            AUDIO_STREAM,
+            (1, SAMPLE_RATE as i32).into(),
+            self.stream_time_base,
+        )
+    }
+}
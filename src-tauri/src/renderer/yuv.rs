@@ -0,0 +1,149 @@
+/// RGB to Y'CbCr 4:2:0 conversion for video export. We do this ourselves rather than letting
+/// swscale pick a matrix, so that the matrix and range we tag the stream with are guaranteed
+/// to be the ones that were actually used, and colors match between the preview and players.
+use ffmpeg_next::{
+    format::Pixel,
+    util::{
+        color::{Primaries, Range, Space, TransferCharacteristic},
+        frame::Video as Frame,
+    },
+};
+use image::{ImageBuffer, Rgba};
+
+use crate::interface::{ColorMatrix, ColorRange, ExportSettings};
+
+impl ColorMatrix {
+    /// luma coefficients (Kr, Kb)
+    fn coefficients(&self) -> (f32, f32) {
+        match self {
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+            ColorMatrix::Bt601 => (0.299, 0.114),
+        }
+    }
+
+    pub fn space(&self) -> Space {
+        match self {
+            ColorMatrix::Bt709 => Space::BT709,
+            ColorMatrix::Bt601 => Space::SMPTE170M,
+        }
+    }
+}
+
+impl ColorRange {
+    pub fn range(&self) -> Range {
+        match self {
+            ColorRange::Limited => Range::MPEG,
+            ColorRange::Full => Range::JPEG,
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn pixel_format(&self) -> Pixel {
+        if self.ten_bit {
+            Pixel::YUV420P10LE
+        } else {
+            Pixel::YUV420P
+        }
+    }
+
+    fn max_code(&self) -> f32 {
+        if self.ten_bit {
+            1023.0
+        } else {
+            255.0
+        }
+    }
+
+    /// (offset, scale) for luma and (offset, scale) for chroma, in code values
+    fn quantization(&self) -> ((f32, f32), (f32, f32)) {
+        let max_code = self.max_code();
+        let shift = (max_code + 1.0) / 256.0;
+        match self.range {
+            ColorRange::Limited => (
+                (16.0 * shift, 219.0 * shift),
+                (128.0 * shift, 224.0 * shift),
+            ),
+            ColorRange::Full => ((0.0, max_code), (128.0 * shift, max_code)),
+        }
+    }
+}
+
+/// Convert a rendered frame into a planar 4:2:0 frame, tagged with the matrix and range from
+/// `settings` and BT.709 primaries and transfer, which frames are always rendered with. The
+/// alpha channel is ignored.
+pub fn rgba_to_yuv_frame(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, settings: &ExportSettings) -> Frame {
+    let (width, height) = img.dimensions();
+    let (kr, kb) = settings.matrix.coefficients();
+    let kg = 1.0 - kr - kb;
+    let ((y_offset, y_scale), (c_offset, c_scale)) = settings.quantization();
+    let max_code = settings.max_code();
+    let quantize = |x: f32| x.round().clamp(0.0, max_code) as u16;
+
+    let to_ycbcr = |px: &Rgba<u8>| -> (f32, f32, f32) {
+        let [r, g, b] = [px[0], px[1], px[2]].map(|c| c as f32 / 255.0);
+        let y = kr * r + kg * g + kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - kb));
+        let cr = (r - y) / (2.0 * (1.0 - kr));
+        (y, cb, cr)
+    };
+
+    let mut frame = Frame::new(settings.pixel_format(), width, height);
+    let bytes_per_sample = if settings.ten_bit { 2 } else { 1 };
+    let write = |plane: &mut [u8], stride: usize, x: usize, y: usize, value: u16| {
+        let i = y * stride + x * bytes_per_sample;
+        if bytes_per_sample == 2 {
+            plane[i..i + 2].copy_from_slice(&value.to_le_bytes());
+        } else {
+            plane[i] = value as u8;
+        }
+    };
+
+    let y_stride = frame.stride(0);
+    for (x, y, px) in img.enumerate_pixels() {
+        let (l, _, _) = to_ycbcr(px);
+        write(
+            frame.data_mut(0),
+            y_stride,
+            x as usize,
+            y as usize,
+            quantize(y_offset + y_scale * l),
+        );
+    }
+
+    // chroma is the average over each 2x2 block of pixels
+    let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+    let (cb_stride, cr_stride) = (frame.stride(1), frame.stride(2));
+    for cy in 0..chroma_height {
+        for cx in 0..chroma_width {
+            let (mut cb, mut cr) = (0.0, 0.0);
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let px = img.get_pixel((cx * 2 + dx).min(width - 1), (cy * 2 + dy).min(height - 1));
+                let (_, pb, pr) = to_ycbcr(px);
+                cb += pb / 4.0;
+                cr += pr / 4.0;
+            }
+            let (cx, cy) = (cx as usize, cy as usize);
+            write(
+                frame.data_mut(1),
+                cb_stride,
+                cx,
+                cy,
+                quantize(c_offset + c_scale * cb),
+            );
+            write(
+                frame.data_mut(2),
+                cr_stride,
+                cx,
+                cy,
+                quantize(c_offset + c_scale * cr),
+            );
+        }
+    }
+
+    frame.set_color_space(settings.matrix.space());
+    frame.set_color_range(settings.range.range());
+    frame.set_color_primaries(Primaries::BT709);
+    frame.set_color_transfer_characteristic(TransferCharacteristic::BT709);
+    frame
+}
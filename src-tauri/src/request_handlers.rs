@@ -1,11 +1,24 @@
-use anyhow::{anyhow, Context, Result};
-use image::{codecs::png::PngEncoder, DynamicImage, ImageEncoder};
+use anyhow::Result;
+use image::{codecs::png::PngEncoder, ImageEncoder};
 
-use std::{collections::HashMap, fmt::Debug, fs::File, io::Cursor, iter, sync::mpsc::Sender};
+use std::{
+    fmt::Debug,
+    sync::mpsc::{self, Sender},
+    time::Duration,
+};
 
 use crate::{
-    interface::VideoDescription,
-    signals::{ExportVideo, MediaResources, Playback, SetFrame, Signal},
+    interface::{
+        Beats, Bounds, ExportSettings, Inspection, LipSync, Overlays, VideoDescription, Waveform,
+        WaveformDetail,
+    },
+    psd_import::{self, PsdImport},
+    resources::load_media_resources,
+    signals::{
+        ExportAudio, ExportVideo, GetBeats, GetBounds, GetLipSync, GetWaveform, Inspect, OnionSkin,
+        Playback, SetFrame, Signal,
+    },
+    svg_import::{self, SvgImport},
 };
 
 fn errstr(e: impl Debug) -> String {
@@ -28,52 +41,8 @@ pub fn update_media_resources(
     res: Vec<(u32, String)>,
 ) -> Result<(), String> {
     (|| -> Result<_> {
-        let supported_image_types = [".png", ".bmp", ".jpg", ".jpeg", ".webp"];
-        let path_to_resource = |path: String| -> Result<DynamicImage> {
-            let pathbuf = dirs::home_dir().unwrap().join(&path);
-
-            if path.ends_with(".json") {
-                let json: HashMap<String, serde_json::Value> = serde_json::from_reader(
-                    File::open(pathbuf).with_context(|| format!("can't open {}", path))?,
-                )?;
-                let img_data = json
-                    .get("img")
-                    .ok_or(anyhow!("missing image resource"))?
-                    .as_str()
-                    .ok_or(anyhow!("image resource isn't a string"))?;
-                let base64 = base64_simd::STANDARD;
-                let img_data_decoded = base64.decode_to_vec(img_data)?;
-                Ok(image::load(
-                    Cursor::new(img_data_decoded),
-                    image::ImageFormat::Png,
-                )?)
-            } else if supported_image_types
-                .into_iter()
-                .any(|ext| path.ends_with(ext))
-            {
-                Ok(image::open(pathbuf).with_context(|| format!("can't open {}", path))?)
-            } else {
-                Err(anyhow!(
-                    "File extension {:?} not recognized. Should be one of {:?}",
-                    pathbuf.extension(),
-                    supported_image_types
-                        .iter()
-                        .cloned()
-                        .chain(iter::once(".json"))
-                        .collect::<Vec<&str>>()
-                ))
-            }
-        };
-
-        let images: Result<HashMap<_, _>> = res
-            .into_iter()
-            .map(|(id, path)| path_to_resource(path).map(|i| (id, i)))
-            .collect();
-
-        signal_tx.send(Signal::UpdateMediaResources(MediaResources {
-            images: images?,
-            sounds: HashMap::new(),
-        }))?;
+        let media_resources = load_media_resources(&dirs::home_dir().unwrap(), res)?;
+        signal_tx.send(Signal::UpdateMediaResources(media_resources))?;
 
         signal_tx.send(Signal::SetPlayback(Playback {
             playing: false,
@@ -131,14 +100,158 @@ pub fn reverse(signal_tx: tauri::State<Sender<Signal>>) -> Result<(), String> {
     playback(signal_tx, true, true, None)
 }
 
+/// Show ghosts of the frames around the current one in the preview, or turn them off if
+/// `enabled` is false. Exports are never affected.
+#[tauri::command]
+pub fn onion_skin(
+    signal_tx: tauri::State<Sender<Signal>>,
+    enabled: bool,
+    before: Option<usize>,
+    after: Option<usize>,
+    opacity: Option<f32>,
+) -> Result<(), String> {
+    let onion_skin = enabled.then(|| OnionSkin {
+        before: before.unwrap_or(2),
+        after: after.unwrap_or(2),
+        opacity: opacity.unwrap_or(0.4).clamp(0.0, 1.0),
+    });
+    signal_tx
+        .send(Signal::SetOnionSkin(onion_skin))
+        .map_err(errstr)
+}
+
+/// Guides to draw over the preview window. Exports are never affected.
+#[tauri::command]
+pub fn overlays(signal_tx: tauri::State<Sender<Signal>>, overlays: Overlays) -> Result<(), String> {
+    signal_tx
+        .send(Signal::SetOverlays(overlays))
+        .map_err(errstr)
+}
+
+/// What's under the point `(x, y)` of the preview window (in pixels, from the top left) in
+/// `frame`: the path of the topmost object there, its bounds and the color of the pixel.
+#[tauri::command]
+pub fn inspect(
+    signal_tx: tauri::State<Sender<Signal>>,
+    frame: usize,
+    x: f32,
+    y: f32,
+) -> Result<Inspection, String> {
+    let (reply, response) = mpsc::channel();
+    signal_tx
+        .send(Signal::Inspect(Inspect { frame, x, y, reply }))
+        .map_err(errstr)?;
+    response
+        .recv_timeout(Duration::from_secs(5))
+        .map_err(errstr)?
+}
+
+/// Bounding boxes of every visible node and object in `frame`, in frame coordinates
+#[tauri::command]
+pub fn bounds(
+    signal_tx: tauri::State<Sender<Signal>>,
+    frame: usize,
+) -> Result<Vec<Bounds>, String> {
+    let (reply, response) = mpsc::channel();
+    signal_tx
+        .send(Signal::GetBounds(GetBounds { frame, reply }))
+        .map_err(errstr)?;
+    response
+        .recv_timeout(Duration::from_secs(5))
+        .map_err(errstr)?
+}
+
+/// Min/max peaks of the sound resource `id`, in buckets that line up with the video's frames
+#[tauri::command]
+pub fn waveform(
+    signal_tx: tauri::State<Sender<Signal>>,
+    id: u32,
+    detail: WaveformDetail,
+) -> Result<Waveform, String> {
+    let (reply, response) = mpsc::channel();
+    signal_tx
+        .send(Signal::GetWaveform(GetWaveform { id, detail, reply }))
+        .map_err(errstr)?;
+    response
+        .recv_timeout(Duration::from_secs(5))
+        .map_err(errstr)?
+}
+
+/// The mouth image id to show in each frame of the dialogue sound `id`, from its start.
+/// Working them out takes a while for long sounds, so it's done here rather than on the
+/// render thread.
+#[tauri::command]
+pub fn lip_sync(
+    signal_tx: tauri::State<Sender<Signal>>,
+    id: u32,
+    settings: LipSync,
+) -> Result<Vec<u32>, String> {
+    if settings.mouths.is_empty() {
+        return Err("no mouths to choose from".to_string());
+    }
+    let (reply, response) = mpsc::channel();
+    signal_tx
+        .send(Signal::GetLipSync(GetLipSync { id, reply }))
+        .map_err(errstr)?;
+    let (audio, fps) = response
+        .recv_timeout(Duration::from_secs(5))
+        .map_err(errstr)??;
+    Ok(crate::lip_sync::lip_sync(&audio, fps, &settings))
+}
+
+/// The tempo of the music `id`, and the frames its beats and onsets fall on, counting from
+/// its start. Like `lip_sync`, the analysis runs here rather than on the render thread.
+#[tauri::command]
+pub fn beats(signal_tx: tauri::State<Sender<Signal>>, id: u32) -> Result<Beats, String> {
+    let (reply, response) = mpsc::channel();
+    signal_tx
+        .send(Signal::GetBeats(GetBeats { id, reply }))
+        .map_err(errstr)?;
+    let (audio, fps) = response
+        .recv_timeout(Duration::from_secs(5))
+        .map_err(errstr)??;
+    Ok(crate::beats::beats(&audio, fps))
+}
+
+/// Turn the svg at `path` (relative to the home directory) into nodes for a video of the
+/// given aspect ratio, see `svg_import`
+#[tauri::command]
+pub fn import_svg(path: String, aspect_ratio: Option<f32>) -> Result<SvgImport, String> {
+    svg_import::import_svg(&dirs::home_dir().unwrap(), &path, aspect_ratio).map_err(errstr)
+}
+
+/// Split the psd at `path` (relative to the home directory) into its layers and a node tree
+/// for a video of the given aspect ratio, see `psd_import`
+#[tauri::command]
+pub fn import_psd(path: String, aspect_ratio: Option<f32>) -> Result<PsdImport, String> {
+    psd_import::import_psd(&dirs::home_dir().unwrap(), &path, aspect_ratio).map_err(errstr)
+}
+
 #[tauri::command]
 pub fn export(
     app_handle: tauri::AppHandle,
     signal_tx: tauri::State<Sender<Signal>>,
     path: String,
+    settings: Option<ExportSettings>,
+) -> Result<(), String> {
+    signal_tx
+        .send(Signal::ExportVideo(ExportVideo {
+            app_handle,
+            path,
+            settings: settings.unwrap_or_default(),
+        }))
+        .map_err(errstr)
+}
+
+/// Mix down the video's sound into a wav file at `path`
+#[tauri::command]
+pub fn export_audio(
+    app_handle: tauri::AppHandle,
+    signal_tx: tauri::State<Sender<Signal>>,
+    path: String,
 ) -> Result<(), String> {
     signal_tx
-        .send(Signal::ExportVideo(ExportVideo { app_handle, path }))
+        .send(Signal::ExportAudio(ExportAudio { app_handle, path }))
         .map_err(errstr)
 }
 
@@ -0,0 +1,255 @@
+/// Loading of media resources (images, sounds, ...) from disk. This is shared between
+/// the `update_media_resources` request handler and anything else that needs to load
+/// resources without going through the UI, like the golden image tests.
+use anyhow::{anyhow, Context, Result};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage, Frame, RgbaImage,
+};
+use resvg::{tiny_skia, usvg};
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Cursor},
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use crate::aseprite::load_aseprite;
+use crate::audio::Audio;
+use crate::signals::{Animation, MediaResources};
+use crate::video_clip::VideoClip;
+
+const SUPPORTED_IMAGE_TYPES: [&str; 6] = [".png", ".bmp", ".jpg", ".jpeg", ".webp", ".gif"];
+const SUPPORTED_FONT_TYPES: [&str; 2] = [".ttf", ".otf"];
+const SUPPORTED_VIDEO_TYPES: [&str; 5] = [".mp4", ".mov", ".mkv", ".webm", ".avi"];
+const SUPPORTED_AUDIO_TYPES: [&str; 4] = [".wav", ".mp3", ".ogg", ".flac"];
+const ASEPRITE_TYPES: [&str; 2] = [".aseprite", ".ase"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    extensions.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Load a single image resource. Besides regular image files, this also accepts the `.json`
+/// files written by the character editor, which store a base64 encoded png under `img`.
+pub fn load_image(path: &Path) -> Result<DynamicImage> {
+    let name = path.to_string_lossy();
+    if name.ends_with(".json") {
+        let json: HashMap<String, serde_json::Value> = serde_json::from_reader(
+            File::open(path).with_context(|| format!("can't open {}", name))?,
+        )?;
+        let img_data = json
+            .get("img")
+            .ok_or(anyhow!("missing image resource"))?
+            .as_str()
+            .ok_or(anyhow!("image resource isn't a string"))?;
+        let base64 = base64_simd::STANDARD;
+        let img_data_decoded = base64.decode_to_vec(img_data)?;
+        Ok(image::load(
+            Cursor::new(img_data_decoded),
+            image::ImageFormat::Png,
+        )?)
+    } else if has_extension(path, &SUPPORTED_IMAGE_TYPES) {
+        Ok(image::open(path).with_context(|| format!("can't open {}", name))?)
+    } else {
+        Err(anyhow!(
+            "File extension {:?} not recognized. Should be one of {:?}",
+            path.extension(),
+            SUPPORTED_IMAGE_TYPES
+                .iter()
+                .chain(SUPPORTED_FONT_TYPES.iter())
+                .chain(SUPPORTED_VIDEO_TYPES.iter())
+                .chain(SUPPORTED_AUDIO_TYPES.iter())
+                .chain(ASEPRITE_TYPES.iter())
+                .chain([".json", ".svg"].iter())
+                .collect::<Vec<_>>()
+        ))
+    }
+}
+
+/// The system's fonts, for text in svgs. Finding them is slow, so it's only done once.
+fn system_fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = usvg::fontdb::Database::new();
+            fonts.load_system_fonts();
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+/// Parse an svg document. Text is turned into paths here, using the system's fonts.
+pub fn load_svg(path: &Path) -> Result<usvg::Tree> {
+    let data = fs::read(path).with_context(|| format!("can't open {}", path.display()))?;
+    let options = usvg::Options {
+        // linked images are relative to the document
+        resources_dir: path.parent().map(Path::to_path_buf),
+        fontdb: system_fonts(),
+        ..Default::default()
+    };
+    usvg::Tree::from_data(&data, &options)
+        .with_context(|| format!("can't parse svg {}", path.display()))
+}
+
+/// The width an svg is rasterized at, before anything is known about how big it's drawn
+pub fn own_width(tree: &usvg::Tree) -> u32 {
+    (tree.size().width().ceil() as u32).max(1)
+}
+
+/// Draw an svg document `width` pixels wide, keeping its aspect ratio
+pub fn rasterize_svg(tree: &usvg::Tree, width: u32) -> Result<DynamicImage> {
+    let size = tree.size();
+    let scale = width as f32 / size.width();
+    let height = (size.height() * scale).ceil().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .with_context(|| format!("can't rasterize an svg at {}x{}", width, height))?;
+    resvg::render(
+        tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    // tiny-skia works with premultiplied alpha, images don't
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(width, height, pixels).unwrap();
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Image sequences don't store any timing, so their frames are shown this long
+const SEQUENCE_FRAME_DURATION: f32 = 1.0 / 24.0;
+/// Like browsers do, frames of animated images with (almost) no delay are shown this long
+const DEFAULT_FRAME_DURATION: f32 = 0.1;
+
+fn animation_from_frames(frames: Vec<Frame>) -> Animation {
+    let durations = frames
+        .iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let seconds = numer as f32 / denom as f32 / 1000.0;
+            if seconds <= 0.01 {
+                DEFAULT_FRAME_DURATION
+            } else {
+                seconds
+            }
+        })
+        .collect();
+    let frames = frames
+        .into_iter()
+        .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+        .collect();
+    Animation {
+        frames,
+        durations,
+        tags: vec![],
+        layers: vec![],
+    }
+}
+
+/// Load a folder of images numbered from 0 (`0.png`, `1.png`, ...), like the ones
+/// the character editor exports, in order
+fn load_sequence(dir: &Path) -> Result<Animation> {
+    let mut numbered = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("can't open {}", dir.display()))? {
+        let path = entry?.path();
+        let number = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<usize>().ok());
+        if let (Some(number), true) = (number, has_extension(&path, &SUPPORTED_IMAGE_TYPES)) {
+            numbered.push((number, path));
+        }
+    }
+    if numbered.is_empty() {
+        return Err(anyhow!("{} has no numbered images", dir.display()));
+    }
+    numbered.sort();
+    let frames = numbered
+        .iter()
+        .map(|(_, path)| load_image(path))
+        .collect::<Result<Vec<_>>>()?;
+    let durations = vec![SEQUENCE_FRAME_DURATION; frames.len()];
+    Ok(Animation {
+        frames,
+        durations,
+        tags: vec![],
+        layers: vec![],
+    })
+}
+
+/// Load an animated image resource, or nothing if `path` is a still image
+fn load_animation(path: &Path) -> Result<Option<Animation>> {
+    if path.is_dir() {
+        return load_sequence(path).map(Some);
+    }
+    if has_extension(path, &ASEPRITE_TYPES) {
+        return load_aseprite(path).map(Some);
+    }
+    let name = path.to_string_lossy().to_lowercase();
+    let open = || -> Result<BufReader<File>> {
+        let file = File::open(path).with_context(|| format!("can't open {}", path.display()))?;
+        Ok(BufReader::new(file))
+    };
+    let frames = if name.ends_with(".gif") {
+        GifDecoder::new(open()?)?.into_frames().collect_frames()?
+    } else if name.ends_with(".png") {
+        let decoder = PngDecoder::new(open()?)?;
+        if !decoder.is_apng() {
+            return Ok(None);
+        }
+        decoder.apng().into_frames().collect_frames()?
+    } else if name.ends_with(".webp") {
+        let decoder = WebPDecoder::new(open()?)?;
+        if !decoder.has_animation() {
+            return Ok(None);
+        }
+        decoder.into_frames().collect_frames()?
+    } else {
+        return Ok(None);
+    };
+    // single frame gifs are just images
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(animation_from_frames(frames)))
+}
+
+/// Load every resource in `res`, where each path is relative to `root`.
+pub fn load_media_resources(root: &Path, res: Vec<(u32, String)>) -> Result<MediaResources> {
+    let mut media_resources = MediaResources::default();
+    for (id, path) in res {
+        let path = root.join(path);
+        if has_extension(&path, &SUPPORTED_FONT_TYPES) {
+            let font = fs::read(&path).with_context(|| format!("can't open {}", path.display()))?;
+            // catch broken fonts here rather than in the middle of rendering
+            ttf_parser::Face::parse(&font, 0)
+                .map_err(|e| anyhow!("can't parse font {}: {}", path.display(), e))?;
+            media_resources.fonts.insert(id, font);
+        } else if has_extension(&path, &[".svg"]) {
+            // drawn at its own size until the renderer knows how big it's used
+            let tree = load_svg(&path)?;
+            let image = rasterize_svg(&tree, own_width(&tree))?;
+            media_resources.images.insert(id, image);
+            media_resources.svgs.insert(id, tree);
+        } else if has_extension(&path, &SUPPORTED_AUDIO_TYPES) {
+            media_resources
+                .sounds
+                .insert(id, Arc::new(Audio::open(&path)?));
+        } else if has_extension(&path, &SUPPORTED_VIDEO_TYPES) {
+            media_resources.videos.insert(id, VideoClip::open(&path)?);
+        } else if let Some(animation) = load_animation(&path)? {
+            media_resources.animations.insert(id, animation);
+        } else {
+            media_resources.images.insert(id, load_image(&path)?);
+        }
+    }
+    Ok(media_resources)
+}
@@ -1,28 +1,90 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Sender, Arc},
+};
 
 use image::DynamicImage;
+use resvg::usvg;
 use tauri::AppHandle;
 
-use crate::interface::VideoDescription;
+use crate::audio::Audio;
+use crate::interface::{
+    Bounds, ExportSettings, Inspection, Overlays, VideoDescription, Waveform, WaveformDetail,
+};
+use crate::video_clip::VideoClip;
 
-pub struct Audio;
+/// An animated image (gif, apng, animated webp, aseprite file or a folder of numbered
+/// images), loaded as one resource
+pub struct Animation {
+    pub frames: Vec<DynamicImage>,
+    /// how long each frame is shown, in seconds
+    pub durations: Vec<f32>,
+    /// named loops within the animation, see `AnimationTime::Tag`
+    pub tags: Vec<AnimationTag>,
+    /// layers that can be drawn on their own, each with an image for every frame. Only
+    /// aseprite files have them.
+    pub layers: Vec<AnimationLayer>,
+}
+
+pub struct AnimationTag {
+    pub name: String,
+    /// first and last frame of the loop, both included
+    pub from: usize,
+    pub to: usize,
+    pub direction: TagDirection,
+}
+
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    /// forward, then back again
+    PingPong,
+}
+
+pub struct AnimationLayer {
+    pub name: String,
+    pub frames: Vec<DynamicImage>,
+}
 
 #[derive(Default)]
 pub struct MediaResources {
     pub images: HashMap<u32, DynamicImage>,
-    pub sounds: HashMap<u32, Audio>,
+    pub animations: HashMap<u32, Animation>,
+    /// svg documents, also in `images` rasterized at the size they're drawn at
+    pub svgs: HashMap<u32, usvg::Tree>,
+    /// decoded in full, see `audio`. Shared, so they can be analysed without holding the
+    /// resources.
+    pub sounds: HashMap<u32, Arc<Audio>>,
+    /// raw font files, parsed when text gets laid out
+    pub fonts: HashMap<u32, Vec<u8>>,
+    pub videos: HashMap<u32, VideoClip>,
 }
 
 pub enum Signal {
     ExportVideo(ExportVideo),
+    ExportAudio(ExportAudio),
     SetPlayback(Playback),
     UpdateVideoDescription(VideoDescription),
-    UpdateMediaResources(MediaResources)
+    UpdateMediaResources(MediaResources),
+    SetOnionSkin(Option<OnionSkin>),
+    SetOverlays(Overlays),
+    Inspect(Inspect),
+    GetBounds(GetBounds),
+    GetWaveform(GetWaveform),
+    GetLipSync(GetLipSync),
+    GetBeats(GetBeats),
 }
 
 pub struct ExportVideo {
-    pub app_handle : AppHandle<>, 
-    pub path : String
+    pub app_handle: AppHandle,
+    pub path: String,
+    pub settings: ExportSettings,
+}
+
+/// Save only the sound of the video, as a wav file
+pub struct ExportAudio {
+    pub app_handle: AppHandle,
+    pub path: String,
 }
 
 pub enum SetFrame {
@@ -35,4 +97,52 @@ pub struct Playback {
     pub playing : bool,
     pub reverse : bool,
     pub frame : Option<SetFrame>
-}
\ No newline at end of file
+}
+
+/// How many frames before and after the current one to show in the preview, and how opaque
+/// the nearest of them are
+pub struct OnionSkin {
+    pub before: usize,
+    pub after: usize,
+    pub opacity: f32,
+}
+
+/// Ask what's at a point of the preview window (in pixels, from the top left) in a frame.
+/// The answer is sent back on `reply`, or why there's none (busy exporting, no such frame).
+pub struct Inspect {
+    pub frame: usize,
+    pub x: f32,
+    pub y: f32,
+    pub reply: Sender<Result<Inspection, String>>,
+}
+
+/// Ask where every node and object of a frame is. The answer is sent back on `reply`, or why
+/// there's none (busy exporting, no such frame).
+pub struct GetBounds {
+    pub frame: usize,
+    pub reply: Sender<Result<Vec<Bounds>, String>>,
+}
+
+/// Ask for the waveform of a sound, at the video's fps. The answer is sent back on `reply`, or
+/// why there's none (busy exporting, no sound with that id).
+pub struct GetWaveform {
+    pub id: u32,
+    pub detail: WaveformDetail,
+    pub reply: Sender<Result<Waveform, String>>,
+}
+
+/// Ask for the sound `id` and the video's fps, to work out mouth shapes for it away from the
+/// render thread. The answer is sent back on `reply`, or why there's none (busy exporting, no
+/// sound with that id).
+pub struct GetLipSync {
+    pub id: u32,
+    pub reply: Sender<Result<(Arc<Audio>, usize), String>>,
+}
+
+/// Ask for the sound `id` and the video's fps, to find its tempo, beats and onsets away from
+/// the render thread. The answer is sent back on `reply`, or why there's none (busy exporting,
+/// no sound with that id).
+pub struct GetBeats {
+    pub id: u32,
+    pub reply: Sender<Result<(Arc<Audio>, usize), String>>,
+}
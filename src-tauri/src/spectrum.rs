@@ -0,0 +1,47 @@
+/// Short-time spectra of sounds, for the analyses in `lip_sync` and `beats`
+use realfft::{RealFftPlanner, RealToComplex};
+
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::audio::SAMPLE_RATE;
+
+pub struct Spectrum {
+    fft: Arc<dyn RealToComplex<f32>>,
+    /// Hann window, so the edges of a slice of sound don't show up as noise
+    window: Vec<f32>,
+}
+
+impl Spectrum {
+    /// For slices of up to `len` samples
+    pub fn new(len: usize) -> Spectrum {
+        let size = len.max(2).next_power_of_two();
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(size);
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+            .collect();
+        Spectrum { fft, window }
+    }
+
+    /// The magnitude of each frequency in `samples`, which are cut or padded with silence to
+    /// the right length
+    pub fn magnitudes(&self, samples: &[f32]) -> Vec<f32> {
+        let mut input = self.fft.make_input_vec();
+        for ((input, sample), window) in input.iter_mut().zip(samples).zip(&self.window) {
+            *input = sample * window;
+        }
+        let mut output = self.fft.make_output_vec();
+        // only fails if the buffers are the wrong size
+        self.fft.process(&mut input, &mut output).unwrap();
+        output.iter().map(|bin| bin.norm()).collect()
+    }
+
+    /// The frequency of bin `bin` of `magnitudes`, in Hz
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * SAMPLE_RATE as f32 / self.fft.len() as f32
+    }
+}
+
+/// Both channels of `samples` averaged
+pub fn mono(samples: &[[f32; 2]]) -> Vec<f32> {
+    samples.iter().map(|[l, r]| (l + r) / 2.0).collect()
+}
@@ -0,0 +1,264 @@
+/// Turning svg documents into node trees the UI can animate part by part, as opposed to svg
+/// resources (see `resources`), which are drawn whole like any other image. Groups become
+/// nodes, paths and text become `Shape`s, and embedded images are saved next to the document
+/// so they can be loaded as image resources.
+///
+/// Some things don't carry over: skews are dropped from group transforms, gradients and
+/// patterns are drawn in a single color, and opacity, clip paths, masks and filters are
+/// ignored.
+use anyhow::Result;
+use resvg::{tiny_skia, usvg};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use std::path::Path;
+
+use crate::node_json::{
+    document_transform, identity, image_transform, images_dir, node, point, save_image,
+    DEFAULT_ASPECT_RATIO,
+};
+use crate::resources::{load_svg, rasterize_svg};
+
+#[derive(Serialize)]
+pub struct SvgImport {
+    /// The whole document as a `Node`, filling the height of the frame. Every node has the
+    /// id of the element it came from as its `name`, which the renderer ignores.
+    pub node: Value,
+    /// Paths of its embedded images, relative to the same directory as the document's path. The
+    /// `Img`s in `node` use their index in this list as their id, to be swapped for the ids
+    /// they get as resources.
+    pub images: Vec<String>,
+}
+
+struct Importer<'a> {
+    /// where images are saved, and what their paths are relative to
+    root: &'a Path,
+    images_dir: String,
+    images: Vec<String>,
+    /// how many elements have been placed, to give each one a higher z than the last
+    count: usize,
+    aspect_ratio: f32,
+}
+
+/// Import the svg document at `path`, relative to `root`, for a video of the given aspect
+/// ratio
+pub fn import_svg(root: &Path, path: &str, aspect_ratio: Option<f32>) -> Result<SvgImport> {
+    let tree = load_svg(&root.join(path))?;
+    let aspect_ratio = aspect_ratio.unwrap_or(DEFAULT_ASPECT_RATIO);
+    let mut importer = Importer {
+        root,
+        images_dir: images_dir(path, "images")?,
+        images: vec![],
+        count: 0,
+        aspect_ratio,
+    };
+    let size = tree.size();
+    let transform = document_transform(size.width(), size.height(), aspect_ratio);
+    let children = importer.children(tree.root(), 0)?;
+    Ok(SvgImport {
+        node: node(tree.root().id(), 0.0, transform, children),
+        images: importer.images,
+    })
+}
+
+impl Importer<'_> {
+    /// The nodes for the children of `group`. Each gets its own node, so each can be moved on
+    /// its own, with a z that keeps them drawn in document order.
+    fn children(&mut self, group: &usvg::Group, group_z: usize) -> Result<Vec<Value>> {
+        let mut children = vec![];
+        for child in group.children() {
+            self.count += 1;
+            let z = self.count;
+            let relative_z = (z - group_z) as f32;
+            match child {
+                usvg::Node::Group(group) => {
+                    let transform = decompose(group.transform());
+                    let grandchildren = self.children(group, z)?;
+                    children.push(node(group.id(), relative_z, transform, grandchildren));
+                }
+                usvg::Node::Path(path) => {
+                    if let Some(shape) = shape(path) {
+                        let leaf = json!({ "Leaf": { "Shape": shape } });
+                        children.push(node(path.id(), relative_z, identity(), vec![leaf]));
+                    }
+                }
+                usvg::Node::Text(text) => {
+                    let transform = decompose(text.flattened().transform());
+                    let glyphs = self.children(text.flattened(), z)?;
+                    children.push(node(text.id(), relative_z, transform, glyphs));
+                }
+                usvg::Node::Image(image) => {
+                    if let Some(img) = self.image(image)? {
+                        children.push(node(image.id(), relative_z, img.0, vec![img.1]));
+                    }
+                }
+            }
+        }
+        Ok(children)
+    }
+
+    /// Save an embedded image, and the transform and `Img` leaf that draw it where it was
+    fn image(&mut self, image: &usvg::Image) -> Result<Option<(Value, Value)>> {
+        if !image.is_visible() {
+            return Ok(None);
+        }
+        let decoded = match image.kind() {
+            usvg::ImageKind::JPEG(data)
+            | usvg::ImageKind::PNG(data)
+            | usvg::ImageKind::GIF(data)
+            | usvg::ImageKind::WEBP(data) => image::load_from_memory(data)?,
+            usvg::ImageKind::SVG(tree) => {
+                rasterize_svg(tree, image.size().width().ceil().max(1.0) as u32)?
+            }
+        };
+        let id = self.images.len();
+        let path = save_image(self.root, &self.images_dir, id, &decoded)?;
+        self.images.push(path);
+
+        let bounds = image.bounding_box();
+        let transform = image_transform(
+            bounds.x(),
+            bounds.y(),
+            bounds.width(),
+            bounds.height(),
+            &decoded,
+            self.aspect_ratio,
+        );
+        let leaf = json!({ "Leaf": { "Img": { "id": id, "subrect": null } } });
+        Ok(Some((transform, leaf)))
+    }
+}
+
+/// Split a transform into a position, scale and rotation (in that order, like `Transform`),
+/// dropping any skew
+fn decompose(t: tiny_skia::Transform) -> Value {
+    // t maps (x, y) to (sx x + kx y + tx, ky x + sy y + ty), and a scale after a rotation
+    // gives sx = scale_x cos, kx = -scale_x sin, ky = scale_y sin, sy = scale_y cos
+    let scale_x = t.sx.hypot(t.kx);
+    let scale_y = if scale_x == 0.0 {
+        t.ky.hypot(t.sy)
+    } else {
+        (t.sx * t.sy - t.kx * t.ky) / scale_x
+    };
+    json!({
+        "pos": point(t.tx, t.ty),
+        "scale": point(scale_x, scale_y),
+        "angle": (-t.kx).atan2(t.sx).to_degrees(),
+    })
+}
+
+/// The `Shape` object for a path, if anything of it is drawn
+fn shape(path: &usvg::Path) -> Option<Value> {
+    if !path.is_visible() {
+        return None;
+    }
+    let fill = path.fill().and_then(|fill| color(fill.paint()));
+    let fill_rule = match path.fill().map(|fill| fill.rule()) {
+        Some(usvg::FillRule::EvenOdd) => "EvenOdd",
+        _ => "NonZero",
+    };
+    let stroke = path.stroke().and_then(|stroke| {
+        Some(json!({
+            "color": color(stroke.paint())?,
+            "thickness": stroke.width().get(),
+        }))
+    });
+    if fill.is_none() && stroke.is_none() {
+        return None;
+    }
+
+    let mut paths = vec![];
+    let mut current: Option<(Value, Vec<Value>)> = None;
+    let mut finish = |current: &mut Option<(Value, Vec<Value>)>, closed: bool| {
+        if let Some((start, segments)) = current.take() {
+            paths.push(json!({ "start": start, "segments": segments, "closed": closed }));
+        }
+    };
+    let pt = |p: tiny_skia::Point| point(p.x, p.y);
+    for segment in path.data().segments() {
+        use tiny_skia::PathSegment;
+        let next = match segment {
+            PathSegment::MoveTo(p) => {
+                finish(&mut current, false);
+                current = Some((pt(p), vec![]));
+                continue;
+            }
+            PathSegment::Close => {
+                finish(&mut current, true);
+                continue;
+            }
+            PathSegment::LineTo(to) => json!({ "Line": pt(to) }),
+            PathSegment::QuadTo(ctrl, to) => {
+                json!({ "Quadratic": { "ctrl": pt(ctrl), "to": pt(to) } })
+            }
+            PathSegment::CubicTo(ctrl1, ctrl2, to) => {
+                json!({ "Cubic": { "ctrl1": pt(ctrl1), "ctrl2": pt(ctrl2), "to": pt(to) } })
+            }
+        };
+        if let Some((_, segments)) = &mut current {
+            segments.push(next);
+        }
+    }
+    finish(&mut current, false);
+
+    Some(json!({ "paths": paths, "fill": fill, "fill_rule": fill_rule, "stroke": stroke }))
+}
+
+/// A paint as a single color. Gradients use their first stop, patterns aren't drawn.
+fn color(paint: &usvg::Paint) -> Option<Value> {
+    let color = match paint {
+        usvg::Paint::Color(color) => *color,
+        usvg::Paint::LinearGradient(gradient) => gradient.stops().first()?.color(),
+        usvg::Paint::RadialGradient(gradient) => gradient.stops().first()?.color(),
+        usvg::Paint::Pattern(_) => return None,
+    };
+    Some(json!({ "r": color.red, "g": color.green, "b": color.blue }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::{Container, FillRule, Object};
+    use crate::node_json::{child_nodes, read_back};
+    use std::fs;
+
+    #[test]
+    fn imported_tree_is_a_node() {
+        let root = std::env::temp_dir().join(format!("svg_import_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("doc.svg"),
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50">
+                <g id="group" transform="translate(10 5) rotate(30) scale(2)">
+                    <path id="ring" fill="red" fill-rule="evenodd"
+                        d="M0 0 H20 V20 H0 Z M5 5 H15 V15 H5 Z"/>
+                    <path id="line" fill="none" stroke="blue" stroke-width="2" d="M0 0 Q5 10 10 0"/>
+                </g>
+            </svg>"#,
+        )
+        .unwrap();
+        let import = import_svg(&root, "doc.svg", None).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let document = read_back(import.node);
+        let [group] = child_nodes(&document)[..] else {
+            panic!("expected one group");
+        };
+        assert!((group.transform.angle - 30.0).abs() < 1e-3);
+        let shapes: Vec<_> = child_nodes(group)
+            .into_iter()
+            .map(|node| match &node.children[..] {
+                [Container::Leaf(Object::Shape(shape))] => shape,
+                _ => panic!("a path isn't a shape"),
+            })
+            .collect();
+        let [ring, line] = &shapes[..] else {
+            panic!("expected 2 shapes");
+        };
+        assert!(matches!(ring.fill_rule, FillRule::EvenOdd));
+        assert_eq!(ring.paths.len(), 2);
+        assert!(ring.stroke.is_none());
+        assert!(matches!(line.fill_rule, FillRule::NonZero));
+        assert!(line.fill.is_none() && line.stroke.is_some());
+    }
+}
@@ -0,0 +1,211 @@
+/// Video files used as resources (live footage, screen recordings, earlier renders). Frames
+/// are decoded on demand, so a clip only costs memory for the few frames around where it's
+/// being shown. Seeking is frame accurate: the decoder jumps to the keyframe before a frame
+/// and decodes forward from there, and playing forward just keeps decoding.
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use ffmpeg_next as ffmpeg;
+use image::RgbaImage;
+
+/// How many decoded frames each clip keeps around. A 1080p frame is 8MB.
+pub const CACHED_FRAMES: usize = 16;
+/// Up to how many frames ahead it's faster to decode forward than to seek. Most encoders put
+/// a keyframe at least every few seconds.
+const MAX_DECODE_AHEAD: usize = 48;
+
+pub struct VideoClip {
+    pub width: u32,
+    pub height: u32,
+    /// frames per second of the clip, which doesn't have to match the video it's used in
+    pub fps: f64,
+    /// number of frames. Containers that don't store it get an estimate from the duration.
+    pub frames: usize,
+    decoder: Mutex<Decoder>,
+}
+
+struct Decoder {
+    input: ffmpeg::format::context::Input,
+    stream: usize,
+    time_base: ffmpeg::Rational,
+    start_time: i64,
+    fps: f64,
+    decoder: ffmpeg::decoder::Video,
+    /// the frame the decoder produces next when nothing is seeked, if known
+    next: Option<usize>,
+    /// most recently used last
+    cache: VecDeque<(usize, Arc<RgbaImage>)>,
+}
+
+impl VideoClip {
+    pub fn open(path: &Path) -> Result<VideoClip> {
+        let name = path.display();
+        let input = ffmpeg::format::input(&path).with_context(|| format!("can't open {}", name))?;
+        let stream = input
+            .streams()
+            .best(ffmpeg::media::Type::Video)
+            .ok_or(anyhow!("{} has no video stream", name))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()
+            .with_context(|| format!("can't decode the video in {}", name))?;
+
+        let rate = stream.avg_frame_rate();
+        let fps = if rate.denominator() != 0 && rate.numerator() > 0 {
+            f64::from(rate)
+        } else {
+            f64::from(stream.rate())
+        };
+        if !fps.is_finite() || fps <= 0.0 {
+            return Err(anyhow!("{} doesn't have a frame rate", name));
+        }
+        let frames = if stream.frames() > 0 {
+            stream.frames() as usize
+        } else if stream.duration() > 0 {
+            (stream.duration() as f64 * f64::from(stream.time_base()) * fps).round() as usize
+        } else {
+            (input.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE) * fps).round() as usize
+        };
+
+        let start_time = match stream.start_time() {
+            ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+            start_time => start_time,
+        };
+        let (index, time_base) = (stream.index(), stream.time_base());
+        Ok(VideoClip {
+            width: decoder.width(),
+            height: decoder.height(),
+            fps,
+            frames: frames.max(1),
+            decoder: Mutex::new(Decoder {
+                input,
+                stream: index,
+                time_base,
+                start_time,
+                fps,
+                decoder,
+                next: Some(0),
+                cache: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Frame `index` of the clip. Past the end, the last frame is shown.
+    pub fn frame(&self, index: usize) -> Result<Arc<RgbaImage>> {
+        let index = index.min(self.frames - 1);
+        let mut decoder = self
+            .decoder
+            .lock()
+            .map_err(|_| anyhow!("video decoder poisoned"))?;
+        decoder.frame(index)
+    }
+}
+
+impl Decoder {
+    fn frame(&mut self, index: usize) -> Result<Arc<RgbaImage>> {
+        if let Some(i) = self.cache.iter().position(|(cached, _)| *cached == index) {
+            let entry = self.cache.remove(i).unwrap();
+            let image = entry.1.clone();
+            self.cache.push_back(entry);
+            return Ok(image);
+        }
+
+        let close_ahead = self
+            .next
+            .is_some_and(|next| next <= index && index - next <= MAX_DECODE_AHEAD);
+        if !close_ahead {
+            self.seek(index)?;
+        }
+        let image = Arc::new(self.decode_until(index)?);
+        if self.cache.len() == CACHED_FRAMES {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((index, image.clone()));
+        Ok(image)
+    }
+
+    /// Jump to the keyframe at or before frame `index`
+    fn seek(&mut self, index: usize) -> Result<()> {
+        let seconds = index as f64 / self.fps + self.start_time as f64 * f64::from(self.time_base);
+        let timestamp = (seconds * f64::from(ffmpeg::ffi::AV_TIME_BASE)) as i64;
+        self.input.seek(timestamp, ..timestamp)?;
+        self.decoder.flush();
+        // we only find out where we landed from the next decoded frame
+        self.next = None;
+        Ok(())
+    }
+
+    fn frame_index(&self, frame: &ffmpeg::frame::Video) -> Option<usize> {
+        let timestamp = frame.timestamp()?;
+        let seconds = (timestamp - self.start_time) as f64 * f64::from(self.time_base);
+        Some((seconds * self.fps).round().max(0.0) as usize)
+    }
+
+    /// Decode frames until frame `index` comes out. If the clip ends first (the frame count
+    /// was an estimate), the last frame is used instead.
+    fn decode_until(&mut self, index: usize) -> Result<RgbaImage> {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        let mut last = None;
+        loop {
+            while self.decoder.receive_frame(&mut decoded).is_ok() {
+                let decoded_index = self.frame_index(&decoded).unwrap_or(self.next.unwrap_or(0));
+                self.next = Some(decoded_index + 1);
+                if decoded_index >= index {
+                    return to_rgba(&decoded);
+                }
+                last = Some(decoded.clone());
+            }
+            match self.input.packets().next() {
+                Some((stream, packet)) if stream.index() == self.stream => {
+                    self.decoder.send_packet(&packet)?
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+
+        // flush out the frames the decoder was holding on to
+        self.decoder.send_eof()?;
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let decoded_index = self.frame_index(&decoded).unwrap_or(0);
+            if decoded_index >= index {
+                self.next = None;
+                return to_rgba(&decoded);
+            }
+            last = Some(decoded.clone());
+        }
+        self.next = None;
+        match last {
+            Some(frame) => to_rgba(&frame),
+            None => Err(anyhow!("no frames left to decode")),
+        }
+    }
+}
+
+fn to_rgba(frame: &ffmpeg::frame::Video) -> Result<RgbaImage> {
+    let (width, height) = (frame.width(), frame.height());
+    let mut scaler = ffmpeg::software::scaling::Context::get(
+        frame.format(),
+        width,
+        height,
+        ffmpeg::format::Pixel::RGBA,
+        width,
+        height,
+        ffmpeg::software::scaling::Flags::BILINEAR,
+    )?;
+    let mut rgba = ffmpeg::frame::Video::empty();
+    scaler.run(frame, &mut rgba)?;
+
+    // rows can be padded, so copy them one by one
+    let stride = rgba.stride(0);
+    let row = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for y in 0..height as usize {
+        pixels.extend_from_slice(&rgba.data(0)[y * stride..y * stride + row]);
+    }
+    RgbaImage::from_raw(width, height, pixels).ok_or(anyhow!("decoded frame has the wrong size"))
+}
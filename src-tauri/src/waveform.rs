@@ -0,0 +1,92 @@
+/// Waveform overviews of sounds for the UI's timeline, measured in buckets that line up with
+/// the video's frames. They're kept until the resources are reloaded.
+use std::collections::HashMap;
+
+use crate::audio::{Audio, SAMPLE_RATE};
+use crate::interface::{Waveform, WaveformDetail};
+
+#[derive(Default)]
+pub struct Waveforms {
+    /// by sound id, fps and buckets per frame
+    cache: HashMap<(u32, usize, usize), Waveform>,
+}
+
+impl Waveforms {
+    pub fn get(&mut self, id: u32, audio: &Audio, fps: usize, detail: WaveformDetail) -> Waveform {
+        if audio.samples.is_empty() {
+            return Waveform {
+                buckets_per_frame: 1,
+                min: vec![],
+                max: vec![],
+            };
+        }
+        let fps = fps.clamp(1, SAMPLE_RATE as usize);
+        // buckets can't be smaller than a sample
+        let samples_per_frame = SAMPLE_RATE as usize / fps;
+        let buckets_per_frame = match detail {
+            WaveformDetail::Buckets(buckets) => {
+                let frames = audio.samples.len() as f32 * fps as f32 / SAMPLE_RATE as f32;
+                (buckets as f32 / frames).round() as usize
+            }
+            WaveformDetail::BucketsPerFrame(buckets_per_frame) => buckets_per_frame,
+        }
+        .clamp(1, samples_per_frame);
+        self.cache
+            .entry((id, fps, buckets_per_frame))
+            .or_insert_with(|| peaks(audio, fps * buckets_per_frame, buckets_per_frame))
+            .clone()
+    }
+
+    /// Forget every waveform, because the sounds were reloaded
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// Measure `audio` in `buckets_per_second` buckets a second, over both channels
+fn peaks(audio: &Audio, buckets_per_second: usize, buckets_per_frame: usize) -> Waveform {
+    // where bucket `i` starts, worked out the same way as the start of a frame in `mixer`
+    let bucket_start = |i: usize| i * SAMPLE_RATE as usize / buckets_per_second;
+    let len = audio.samples.len();
+    let mut min = vec![];
+    let mut max = vec![];
+    let mut i = 0;
+    while bucket_start(i) < len {
+        let bucket = &audio.samples[bucket_start(i)..bucket_start(i + 1).min(len)];
+        let samples = bucket.iter().flatten();
+        min.push(samples.clone().copied().fold(0.0, f32::min));
+        max.push(samples.copied().fold(0.0, f32::max));
+        i += 1;
+    }
+    Waveform {
+        buckets_per_frame,
+        min,
+        max,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waveform(samples: usize, detail: WaveformDetail) -> Waveform {
+        let audio = Audio {
+            samples: vec![[0.5, -0.5]; samples],
+        };
+        Waveforms::default().get(0, &audio, 24, detail)
+    }
+
+    #[test]
+    fn empty_sounds_have_empty_waveforms() {
+        let waveform = waveform(0, WaveformDetail::Buckets(100));
+        assert!(waveform.min.is_empty() && waveform.max.is_empty());
+    }
+
+    #[test]
+    fn buckets_hold_at_least_a_sample() {
+        let waveform = waveform(4000, WaveformDetail::BucketsPerFrame(usize::MAX));
+        assert_eq!(waveform.buckets_per_frame, 2000);
+        assert_eq!(waveform.min, vec![-0.5; 4000]);
+        assert_eq!(waveform.max, vec![0.5; 4000]);
+    }
+}
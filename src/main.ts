@@ -213,4 +213,10 @@ listen('encoded-frame', (event) => {
     } else {
         frameCounter.textContent = (event.payload as number + 1).toString() + " frame(s) out of " + lastFrame + " completed"
     }
-})
\ No newline at end of file
+})
+
+listen('export-failed', (event) => {
+    let frameCounter: HTMLElement = document.getElementById('status')!
+    frameCounter.textContent = "export failed ❌"
+    alert("error: " + event.payload)
+})